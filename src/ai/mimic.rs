@@ -1,19 +1,27 @@
-use lancedb::connection::Connection;
-use lancedb::table::Table;
-use lancedb::connect;
+#[cfg(feature = "semantic-history")]
+use lancedb::{connect, connection::Connection, query::{ExecutableQuery, QueryBase}, table::Table};
+#[cfg(feature = "semantic-history")]
 use arrow_array::{RecordBatch, StringArray, FixedSizeListArray, Float32Array, RecordBatchIterator, ArrayRef, Array};
+#[cfg(feature = "semantic-history")]
 use arrow_schema::{Schema, Field, DataType, ArrowError};
-use anyhow::{Result, anyhow};
+#[cfg(feature = "semantic-history")]
+use futures::TryStreamExt;
+#[cfg(feature = "semantic-history")]
 use std::sync::Arc;
+use anyhow::{Result, anyhow};
 use std::path::PathBuf;
-use futures::TryStreamExt;
-use lancedb::query::{ExecutableQuery, QueryBase};
 
+#[cfg(feature = "semantic-history")]
 pub struct MimicManager {
     uri: String,
     table_name: String,
 }
 
+/// Built without the `semantic-history` feature: nothing is stored and searches fail.
+#[cfg(not(feature = "semantic-history"))]
+pub struct MimicManager;
+
+#[cfg(feature = "semantic-history")]
 impl MimicManager {
     pub fn new() -> Self {
         let mut path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
@@ -110,7 +118,26 @@ impl MimicManager {
     }
 }
 
-#[cfg(test)]
+#[cfg(not(feature = "semantic-history"))]
+impl MimicManager {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn new_at_path(_path: PathBuf) -> Self {
+        Self
+    }
+
+    pub async fn add_command(&self, _command: &str, _vector: Vec<f32>) -> Result<()> {
+        Ok(())
+    }
+
+    pub async fn search(&self, _vector: Vec<f32>, _limit: usize) -> Result<Vec<String>> {
+        Err(anyhow!("semantic history isn't available: chev was built without the semantic-history feature"))
+    }
+}
+
+#[cfg(all(test, feature = "semantic-history"))]
 mod tests {
     use super::*;
     use tempfile::tempdir;
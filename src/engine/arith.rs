@@ -0,0 +1,422 @@
+use crate::engine::env::EnvManager;
+use anyhow::{Result, anyhow};
+use std::fmt;
+
+// Arithmetic for `$(( ... ))`, `let` and `math`: C-style operators over integers and floats,
+// with bare names read from and assigned to shell variables.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    /// Zero is false, like a C condition.
+    pub fn is_true(self) -> bool {
+        match self {
+            Number::Int(i) => i != 0,
+            Number::Float(f) => f != 0.0,
+        }
+    }
+
+    fn as_float(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(i) => write!(f, "{}", i),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl From<bool> for Number {
+    fn from(b: bool) -> Self {
+        Number::Int(b as i64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(Number),
+    Name(String),
+    Op(&'static str),
+}
+
+/// Longest first, so `<<=` wins over `<<` and `<`.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "+=", "-=", "*=", "/=", "%=", "&=", "^=", "|=",
+    "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^", "|", "?", ":", "(", ")", ",",
+];
+
+/// Binary operators from loosest to tightest; `**` is handled separately since it groups right.
+const BINARY_LEVELS: &[&[&str]] = &[
+    &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", "<=", ">", ">="],
+    &["<<", ">>"], &["+", "-"], &["*", "/", "%"],
+];
+
+const ASSIGNMENT_OPS: &[&str] = &["=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|="];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expr;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '.').unwrap_or(rest.len());
+            tokens.push(Token::Num(parse_number(&rest[..len])?));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(anyhow!("syntax error: unexpected '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// `42`, `0x1f`, `1.5` or `.5`.
+fn parse_number(text: &str) -> Result<Number> {
+    let parsed = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok().map(Number::Int)
+    } else if text.contains('.') {
+        text.parse().ok().map(Number::Float)
+    } else {
+        text.parse().ok().map(Number::Int)
+    };
+    parsed.ok_or_else(|| anyhow!("{}: invalid number", text))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(Number),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    /// `name = e`, or `name += e` and friends with the operator kept
+    Assign(String, &'static str, Box<Expr>),
+    /// `++name`, `name--` and so on
+    Step { name: String, delta: i64, prefix: bool },
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        if self.eat(op) { Ok(()) } else { Err(self.unexpected()) }
+    }
+
+    fn unexpected(&self) -> anyhow::Error {
+        match self.peek() {
+            Some(Token::Num(n)) => anyhow!("syntax error near '{}'", n),
+            Some(Token::Name(name)) => anyhow!("syntax error near '{}'", name),
+            Some(Token::Op(op)) => anyhow!("syntax error near '{}'", op),
+            None => anyhow!("syntax error: expression ends too soon"),
+        }
+    }
+
+    fn comma(&mut self) -> Result<Expr> {
+        let mut expr = self.assignment()?;
+        while self.eat(",") {
+            expr = Expr::Comma(Box::new(expr), Box::new(self.assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) = (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+            && ASSIGNMENT_OPS.contains(op)
+        {
+            let (name, op) = (name.clone(), *op);
+            self.pos += 2;
+            return Ok(Expr::Assign(name, op, Box::new(self.assignment()?)));
+        }
+        self.ternary()
+    }
+
+    fn ternary(&mut self) -> Result<Expr> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.assignment()?;
+        self.expect(":")?;
+        let otherwise = self.assignment()?;
+        Ok(Expr::Ternary(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        let Some(ops) = BINARY_LEVELS.get(level) else { return self.power() };
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| ops.contains(op)) {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.binary(level + 1)?));
+        }
+        Ok(left)
+    }
+
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.unary()?;
+        if self.eat("**") {
+            return Ok(Expr::Binary("**", Box::new(base), Box::new(self.power()?)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek_op() {
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                let Some(Token::Name(name)) = self.peek().cloned() else { return Err(self.unexpected()) };
+                self.pos += 1;
+                Ok(Expr::Step { name, delta: if op == "++" { 1 } else { -1 }, prefix: true })
+            }
+            Some(op @ ("-" | "+" | "!" | "~")) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        match self.peek().cloned() {
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Expr::Num(n))
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                match self.peek_op() {
+                    Some(op @ ("++" | "--")) => {
+                        self.pos += 1;
+                        Ok(Expr::Step { name, delta: if op == "++" { 1 } else { -1 }, prefix: false })
+                    }
+                    _ => Ok(Expr::Var(name)),
+                }
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+/// Evaluates `expr`, reading and assigning variables in `env`. An empty expression is 0.
+pub fn evaluate(expr: &str, env: &mut EnvManager) -> Result<Number> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(Number::Int(0));
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let ast = parser.comma()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(parser.unexpected());
+    }
+    eval(&ast, env)
+}
+
+/// A variable's value as a number. Unset and empty count as 0.
+fn lookup(name: &str, env: &EnvManager) -> Result<Number> {
+    let value = env.get_var(name).map(|v| v.trim()).unwrap_or("");
+    if value.is_empty() {
+        return Ok(Number::Int(0));
+    }
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let n = parse_number(digits).map_err(|_| anyhow!("{}: not a number: {}", name, value))?;
+    Ok(if negative { apply("-", Number::Int(0), n)? } else { n })
+}
+
+fn eval(expr: &Expr, env: &mut EnvManager) -> Result<Number> {
+    match expr {
+        Expr::Num(n) => Ok(*n),
+        Expr::Var(name) => lookup(name, env),
+        Expr::Unary(op, operand) => {
+            let value = eval(operand, env)?;
+            match (*op, value) {
+                ("-", _) => apply("-", Number::Int(0), value),
+                ("+", _) => Ok(value),
+                ("!", _) => Ok((!value.is_true()).into()),
+                ("~", Number::Int(i)) => Ok(Number::Int(!i)),
+                (op, _) => Err(anyhow!("{}: integer operand required", op)),
+            }
+        }
+        // Short-circuit like C, so `x && y++` only bumps y when x is set
+        Expr::Binary("&&", left, right) => Ok((eval(left, env)?.is_true() && eval(right, env)?.is_true()).into()),
+        Expr::Binary("||", left, right) => Ok((eval(left, env)?.is_true() || eval(right, env)?.is_true()).into()),
+        Expr::Binary(op, left, right) => {
+            let left = eval(left, env)?;
+            let right = eval(right, env)?;
+            apply(op, left, right)
+        }
+        Expr::Assign(name, op, value) => {
+            let value = eval(value, env)?;
+            let value = match op.strip_suffix('=').filter(|op| !op.is_empty()) {
+                Some(op) => apply(op, lookup(name, env)?, value)?,
+                None => value,
+            };
+            env.set_var(name.clone(), value.to_string());
+            Ok(value)
+        }
+        Expr::Step { name, delta, prefix } => {
+            let old = lookup(name, env)?;
+            let new = apply("+", old, Number::Int(*delta))?;
+            env.set_var(name.clone(), new.to_string());
+            Ok(if *prefix { new } else { old })
+        }
+        Expr::Ternary(condition, then, otherwise) => {
+            if eval(condition, env)?.is_true() { eval(then, env) } else { eval(otherwise, env) }
+        }
+        Expr::Comma(first, second) => {
+            eval(first, env)?;
+            eval(second, env)
+        }
+    }
+}
+
+/// A binary operator. Integers stay integers (wrapping like C); a float on either side makes a float.
+fn apply(op: &str, left: Number, right: Number) -> Result<Number> {
+    use Number::{Float, Int};
+    let result = match (left, right) {
+        (Int(a), Int(b)) => match op {
+            "+" => Int(a.wrapping_add(b)),
+            "-" => Int(a.wrapping_sub(b)),
+            "*" => Int(a.wrapping_mul(b)),
+            "/" | "%" if b == 0 => return Err(anyhow!("division by zero")),
+            "/" => Int(a.wrapping_div(b)),
+            "%" => Int(a.wrapping_rem(b)),
+            "**" if b < 0 => Float((a as f64).powf(b as f64)),
+            "**" => Int(a.wrapping_pow(b.min(u32::MAX as i64) as u32)),
+            "<<" => Int(a.wrapping_shl(b as u32)),
+            ">>" => Int(a.wrapping_shr(b as u32)),
+            "&" => Int(a & b),
+            "|" => Int(a | b),
+            "^" => Int(a ^ b),
+            _ => compare(op, a.cmp(&b))?,
+        },
+        _ => {
+            let (a, b) = (left.as_float(), right.as_float());
+            match op {
+                "+" => Float(a + b),
+                "-" => Float(a - b),
+                "*" => Float(a * b),
+                "/" | "%" if b == 0.0 => return Err(anyhow!("division by zero")),
+                "/" => Float(a / b),
+                "%" => Float(a % b),
+                "**" => Float(a.powf(b)),
+                "<<" | ">>" | "&" | "|" | "^" => return Err(anyhow!("{}: integer operands required", op)),
+                _ => compare(op, a.partial_cmp(&b).ok_or_else(|| anyhow!("can't compare NaN"))?)?,
+            }
+        }
+    };
+    Ok(result)
+}
+
+fn compare(op: &str, ordering: std::cmp::Ordering) -> Result<Number> {
+    use std::cmp::Ordering::*;
+    let result = match op {
+        "==" => ordering == Equal,
+        "!=" => ordering != Equal,
+        "<" => ordering == Less,
+        "<=" => ordering != Greater,
+        ">" => ordering == Greater,
+        ">=" => ordering != Less,
+        _ => return Err(anyhow!("{}: unknown operator", op)),
+    };
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(expr: &str, env: &mut EnvManager) -> String {
+        evaluate(expr, env).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_operators_and_precedence() {
+        let mut env = EnvManager::new();
+        assert_eq!(calc("1 + 2 * 3", &mut env), "7");
+        assert_eq!(calc("(1 + 2) * 3", &mut env), "9");
+        assert_eq!(calc("2 ** 3 ** 2", &mut env), "512");
+        assert_eq!(calc("-7 / 2", &mut env), "-3");
+        assert_eq!(calc("7 % 3 == 1 && 1 << 4 > 15", &mut env), "1");
+        assert_eq!(calc("0x10 | 1", &mut env), "17");
+        assert_eq!(calc("10 / 4.0", &mut env), "2.5");
+        assert_eq!(calc("3 > 2 ? 10 : 20", &mut env), "10");
+        assert_eq!(calc("!0 + ~0", &mut env), "0");
+        assert_eq!(calc("", &mut env), "0");
+        assert!(evaluate("1 / 0", &mut env).is_err());
+        assert!(evaluate("1 +", &mut env).is_err());
+        assert!(evaluate("2 3", &mut env).is_err());
+        assert!(evaluate("1.5 & 1", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_variables_and_assignment() {
+        let mut env = EnvManager::new();
+        env.set_var("CHEV_ARITH_N".to_string(), "5".to_string());
+        assert_eq!(calc("CHEV_ARITH_N * 2", &mut env), "10");
+        assert_eq!(calc("CHEV_ARITH_UNSET + 1", &mut env), "1");
+
+        assert_eq!(calc("CHEV_ARITH_N += 3", &mut env), "8");
+        assert_eq!(calc("CHEV_ARITH_N++", &mut env), "8");
+        assert_eq!(calc("--CHEV_ARITH_N", &mut env), "8");
+        assert_eq!(calc("CHEV_ARITH_I = CHEV_ARITH_J = 2, CHEV_ARITH_I + CHEV_ARITH_J", &mut env), "4");
+        assert_eq!(env.get_var("CHEV_ARITH_N").map(String::as_str), Some("8"));
+
+        // The right side of && only runs when it's needed
+        assert_eq!(calc("0 && CHEV_ARITH_N++", &mut env), "0");
+        assert_eq!(env.get_var("CHEV_ARITH_N").map(String::as_str), Some("8"));
+
+        env.set_var("CHEV_ARITH_WORD".to_string(), "abc".to_string());
+        assert!(evaluate("CHEV_ARITH_WORD + 1", &mut env).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use crate::ai::AiChecker;
+use crate::engine::executor::{ollama_model, on_path};
+use std::io::Write;
+use std::process::Stdio;
+
+pub struct Ai;
+
+impl Builtin for Ai {
+    fn name(&self) -> &'static str {
+        "ai"
+    }
+
+    fn usage(&self) -> &'static str {
+        "ai <command>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Ask, fix and search with the local AI model"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("chat", "Open a persistent AI chat sidebar"),
+            ("browse", "Browse and summarize a webpage in the sidebar"),
+            ("ask", "Ask the AI for advice or help"),
+            ("fix", "Fix the last failed command"),
+            ("search", "Search history semantically"),
+            ("status", "Check AI system health"),
+            ("setup", "Install the required AI model and modern tools"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let teal = "\x1b[38;2;110;209;195m";
+            let reset = "\x1b[0m";
+            let gray = "\x1b[90m";
+
+            match args.get(1).map(|s| s.as_str()) {
+                Some("chat") => {
+                    let gray = "\x1b[90m";
+                    let reset = "\x1b[0m";
+                    writeln!(io.stdout, "{}🐕 Opening AI Chat Sidebar...{}", gray, reset)?;
+
+                    let chev_path = std::env::current_exe()
+                        .map(|p| p.to_string_lossy().to_string())
+                        .unwrap_or_else(|_| "chev".to_string());
+
+                    crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::SplitPane {
+                        direction: "right".to_string(),
+                        ratio: 0.3,
+                        command: format!("{} ai chat --internal", chev_path)
+                    });
+                }
+                Some("browse") => {
+                    if let Some(url) = args.get(2) {
+                        let gray = "\x1b[90m";
+                        let reset = "\x1b[0m";
+                        writeln!(io.stdout, "{}🐕 Opening AI Browser for {}...{}", gray, url, reset)?;
+
+                        let chev_path = std::env::current_exe()
+                            .map(|p| p.to_string_lossy().to_string())
+                            .unwrap_or_else(|_| "chev".to_string());
+
+                        crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::SplitPane {
+                            direction: "right".to_string(),
+                            ratio: 0.3,
+                            command: format!("{} internal browse {}", chev_path, url)
+                        });
+                    } else {
+                        writeln!(io.stdout, "Usage: ai browse <url>")?;
+                    }
+                }
+                Some("ask") => {
+                    let prompt = args[2..].join(" ");
+                    if prompt.is_empty() {
+                        writeln!(io.stdout, "Usage: ai ask <your question>")?;
+                        return Ok(0);
+                    }
+
+                    writeln!(io.stdout, "{}🐕 Chev is thinking...{}", gray, reset)?;
+
+                    let model = ollama_model(ctx.env);
+                    let client = crate::ai::OllamaClient::new(model);
+
+                    match client.generate(prompt, false).await {
+                        Ok(response) => {
+                            writeln!(io.stdout, "{}🤖 AI:{} {}", teal, reset, response)?;
+                            crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::Notify {
+                                title: "AI Answered".to_string(),
+                                message: "Chev has finished thinking.".to_string()
+                            });
+                        }
+                        Err(e) => {
+                            writeln!(io.stderr, "{}❌ Error:{} {}", teal, reset, e)?;
+                        }
+                    }
+                }
+                Some("fix") => {
+                    let (last_cmd, last_err) = {
+                        let macros = ctx.macros.lock().unwrap();
+                        match &macros.last_error {
+                            Some(err) => err.clone(),
+                            None => {
+                                writeln!(io.stdout, "{}No failed command found to fix.{}", gray, reset)?;
+                                return Ok(0);
+                            }
+                        }
+                    };
+
+                    writeln!(io.stdout, "{}🐕 Analyzing last failure...{}", gray, reset)?;
+
+                    let prompt = format!(
+                        "The user ran: `{}`\nIt failed with this error:\n```\n{}\n```\nProvide a fixed command in JSON format: {{\"fixed_command\": \"...\"}}. Only return the JSON.",
+                        last_cmd, last_err
+                    );
+
+                    let model = ollama_model(ctx.env);
+                    let client = crate::ai::OllamaClient::new(model);
+
+                    match client.generate(prompt, true).await {
+                        Ok(response) => {
+                            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&response) {
+                                if let Some(fixed) = json["fixed_command"].as_str() {
+                                    writeln!(io.stdout, "{}💡 Suggestion:{} {}", teal, reset, fixed)?;
+                                    let mut macros = ctx.macros.lock().unwrap();
+                                    macros.last_suggestion = Some(fixed.to_string());
+                                    crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::Notify {
+                                        title: "AI Fix Suggestion".to_string(),
+                                        message: fixed.to_string()
+                                    });
+                                }
+                            } else {
+                                writeln!(io.stdout, "{}AI returned an invalid response.{}", gray, reset)?;
+                            }
+                        }
+                        Err(e) => {
+                            writeln!(io.stderr, "{}❌ Error:{} {}", teal, reset, e)?;
+                        }
+                    }
+                }
+                Some("search") => {
+                    let query = args[2..].join(" ");
+                    if query.is_empty() {
+                        writeln!(io.stdout, "Usage: ai search <description of a past command>")?;
+                        return Ok(0);
+                    }
+
+                    writeln!(io.stdout, "{}🔍 Searching semantic history...{}", gray, reset)?;
+
+                    let model = ollama_model(ctx.env);
+                    let client = crate::ai::OllamaClient::new(model);
+                    let mimic = crate::ai::MimicManager::new();
+
+                    match client.embeddings(query.clone()).await {
+                        Ok(vector) => {
+                            match mimic.search(vector, 5).await {
+                                Ok(results) => {
+                                    if results.is_empty() {
+                                        writeln!(io.stdout, "{}No matching commands found.{}", gray, reset)?;
+                                    } else {
+                                        writeln!(io.stdout, "{}🎯 Top matches:{}", teal, reset)?;
+                                        for (i, res) in results.iter().enumerate() {
+                                            writeln!(io.stdout, "  {}. {}", i + 1, res)?;
+                                        }
+                                        // Set the first result as suggestion
+                                        let mut macros = ctx.macros.lock().unwrap();
+                                        macros.last_suggestion = Some(results[0].clone());
+                                        writeln!(io.stdout, "{}💡 Hit Tab to use the top match.{}", gray, reset)?;
+
+                                        crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::Notify {
+                                            title: "Search Complete".to_string(),
+                                            message: format!("Found {} matches for '{}'", results.len(), query)
+                                        });
+                                    }
+                                }
+                                Err(e) => writeln!(io.stderr, "{}❌ Search error:{} {}", teal, reset, e)?,
+                            }
+                        }
+                        Err(e) => writeln!(io.stderr, "{}❌ Embedding error:{} {}", teal, reset, e)?,
+                    }
+                }
+                Some("status") => {
+                    let checker = AiChecker::new();
+                    let model_name = ollama_model(ctx.env);
+                    let running = checker.is_ollama_running().await;
+
+                    writeln!(io.stdout, "{}📊 AI Status:{}", teal, reset)?;
+                    if running {
+                        writeln!(io.stdout, "  Ollama: {}Running{}", "\x1b[32m", reset)?;
+                        let has_model = checker.has_model(&model_name).await;
+                        if has_model {
+                            writeln!(io.stdout, "  Model ({}): {}Installed{}", model_name, "\x1b[32m", reset)?;
+                            writeln!(io.stdout, "  Ready: {}YES{}", "\x1b[32m", reset)?;
+                        } else {
+                            writeln!(io.stdout, "  Model ({}): {}Not Found{}", model_name, "\x1b[31m", reset)?;
+                            writeln!(io.stdout, "  Ready: {}NO (Run 'ai setup'){}", "\x1b[31m", reset)?;
+                        }
+                    } else {
+                        writeln!(io.stdout, "  Ollama: {}Not Running{}", "\x1b[31m", reset)?;
+                        writeln!(io.stdout, "  Ready: {}NO (Start Ollama application){}", "\x1b[31m", reset)?;
+                    }
+                }
+                Some("setup") => {
+                    let blue = "\x1b[38;2;67;147;255m";
+                    let checker = AiChecker::new();
+                    let model_name = ollama_model(ctx.env);
+
+                    writeln!(io.stdout, "{}🚀 Starting Global Sev Setup...{}", blue, reset)?;
+
+                    // 1. LLM Setup
+                    if checker.is_ollama_running().await {
+                        if !checker.has_model(&model_name).await {
+                            writeln!(io.stdout, "{}⏳ Pulling model '{}'... this might take a while.{}", teal, model_name, reset)?;
+                            let _ = checker.pull_model(&model_name).await;
+                        } else {
+                            writeln!(io.stdout, "{}✅ AI Model already installed.{}", "\x1b[32m", reset)?;
+                        }
+                    } else {
+                        writeln!(io.stdout, "{}⚠️  Ollama not running, skipping model pull.{}", "\x1b[33m", reset)?;
+                    }
+
+                    // 2. Modern Tools Setup (Isolated Environment)
+                    writeln!(io.stdout, "\n{}📦 Checking Modern Tooling...{}", blue, reset)?;
+                    let tools = vec![
+                        ("eza", "eza"), ("zoxide", "zoxide"), ("fd-find", "fd"),
+                        ("du-dust", "dust"), ("rip-bin", "rip"), ("xcp", "xcp"),
+                        ("broot", "broot"), ("lfs", "lfs"), ("miniserve", "miniserve"),
+                        ("bat", "bat"), ("mdcat", "mdcat"), ("ripgrep", "rg"),
+                        ("sd", "sd"), ("git-delta", "delta"), ("jql", "jql"),
+                        ("qsv", "qsv"), ("tealdeer", "tldr"), ("heh", "heh"),
+                        ("lemmeknow", "lemmeknow"), ("kibi", "kibi"), ("bottom", "btm"),
+                        ("procs", "procs"), ("hyperfine", "hyperfine"), ("just", "just"),
+                        ("hwatch", "hwatch"), ("doggo", "doggo"),
+                        ("gping", "gping"), ("xh", "xh"), ("fend", "fend"), ("ouch", "ouch")
+                    ];
+
+                    let home = dirs::home_dir().unwrap();
+                    let isolated_root = home.join(".chev");
+
+                    for (crate_name, bin_name) in tools {
+                        if !on_path(bin_name, ctx.env) {
+                            writeln!(io.stdout, "{}⏳ Installing {}...{}", gray, crate_name, reset)?;
+                            let mut cmd = std::process::Command::new("cargo");
+                            cmd.arg("install")
+                                .arg("--root")
+                                .arg(&isolated_root)
+                                .arg(crate_name);
+
+                            if crate_name == "qsv" {
+                                cmd.arg("--locked").arg("--version").arg("14.0.0");
+                            }
+
+                            let status = cmd
+                                .stdout(Stdio::inherit())
+                                .stderr(Stdio::inherit())
+                                .status();
+
+                            if status.is_ok() && status.unwrap().success() {
+                                writeln!(io.stdout, "  {}✅ {} installed.{}", "\x1b[32m", crate_name, reset)?;
+                            } else {
+                                writeln!(io.stdout, "  {}❌ Failed to install {}.{}", "\x1b[31m", crate_name, reset)?;
+                            }
+                        } else {
+                            writeln!(io.stdout, "  {}✅ {} is ready.{}", "\x1b[32m", bin_name, reset)?;
+                        }
+                    }
+
+                    writeln!(io.stdout, "\n{}✨ Chev Setup Complete! Welcome to the modern age.{}", teal, reset)?;
+                }
+                _ => {
+                    writeln!(io.stdout, "{}🤖 Chev AI Help:{}", teal, reset)?;
+                    writeln!(io.stdout, "  ai chat          - Open a persistent AI chat sidebar.")?;
+                    writeln!(io.stdout, "  ai browse <url>  - Browse and summarize a webpage in the sidebar.")?;
+                    writeln!(io.stdout, "  ai ask <prompt>  - Ask the AI for advice or help.")?;
+                    writeln!(io.stdout, "  ai fix           - Fix the last failed command.")?;
+                    writeln!(io.stdout, "  ai search <desc> - Search history semantically.")?;
+                    writeln!(io.stdout, "  ai status        - Check AI system health.")?;
+                    writeln!(io.stdout, "  ai setup         - Install the required AI model and modern tools.")?;
+                }
+            }
+            Ok(0)
+        })
+    }
+}
@@ -0,0 +1,142 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use crate::engine::executor::on_path;
+use std::io::Write;
+
+pub struct Chev;
+
+impl Builtin for Chev {
+    fn name(&self) -> &'static str {
+        "chev"
+    }
+
+    fn usage(&self) -> &'static str {
+        "chev <command>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Install, rebuild or remove chev itself"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("install", "Symlink chev to /usr/local/bin/chev"),
+            ("uninstall", "Wipe all tools, configs, and the link"),
+            ("cleanup", "Reset history, suggestions, and macros"),
+            ("build", "Recompile the shell from source"),
+            ("setup", "Guide for full system setup"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let teal = "\x1b[38;2;110;209;195m";
+            let reset = "\x1b[0m";
+            let gray = "\x1b[90m";
+            let red = "\x1b[31m";
+
+            match args.get(1).map(|s| s.as_str()) {
+                Some("install") => {
+                    let current_exe = std::env::current_exe()?;
+                    let target = "/usr/local/bin/chev";
+
+                    writeln!(io.stdout, "{}📦 Installing Chev...{}", gray, reset)?;
+
+                    // 1. Dependency Check: Protobuf
+                    if !on_path("protoc", ctx.env) {
+                        writeln!(io.stdout, "{}🛠️  Protobuf not found (required for AI memory). Installing...{}", gray, reset)?;
+                        let status = std::process::Command::new("brew")
+                            .args(&["install", "protobuf"])
+                            .status();
+                        if status.is_ok() { writeln!(io.stdout, "{}✅ Protobuf installed.{}", "\x1b[32m", reset)?; }
+                    }
+
+                    // 2. Dependency Check: Ollama
+                    if !on_path("ollama", ctx.env) {
+                        writeln!(io.stdout, "{}🧠 Ollama not found. Installing...{}", gray, reset)?;
+                        let status = std::process::Command::new("brew")
+                            .args(&["install", "ollama"])
+                            .status();
+                        if status.is_ok() { writeln!(io.stdout, "{}✅ Ollama installed.{}", "\x1b[32m", reset)?; }
+                    }
+
+                    writeln!(io.stdout, "{}🔗 Symlinking to {}...{}", gray, target, reset)?;
+
+                    let status = std::process::Command::new("sudo")
+                        .arg("ln")
+                        .arg("-sf")
+                        .arg(&current_exe)
+                        .arg(target)
+                        .status();
+
+                    if status.is_ok() && status.unwrap().success() {
+                        writeln!(io.stdout, "{}✅ Chev installed successfully! Type 'chev' to launch anytime.{}", "\x1b[32m", reset)?;
+                    } else {
+                        writeln!(io.stdout, "{}❌ Installation failed. Check sudo permissions.{}", red, reset)?;
+                    }
+                }
+                Some("uninstall") => {
+                    writeln!(io.stdout, "{}🧨 Full Uninstall initiated...{}", red, reset)?;
+
+                    let _ = std::process::Command::new("sudo")
+                        .arg("rm")
+                        .arg("/usr/local/bin/chev")
+                        .status();
+
+                    if let Some(home) = dirs::home_dir() {
+                        let chev_dir = home.join(".chev");
+                        if chev_dir.exists() {
+                            let _ = std::fs::remove_dir_all(chev_dir);
+                            writeln!(io.stdout, "{}✅ Atomic wipe of ~/.chev successful.{}", gray, reset)?;
+                        }
+                    }
+
+                    writeln!(io.stdout, "{}✨ Chev has been cleared. Goodbye!{}", teal, reset)?;
+                    std::process::exit(0);
+                }
+                Some("cleanup") => {
+                    writeln!(io.stdout, "{}🧹 Cleaning up persistent state...{}", gray, reset)?;
+
+                    if let Some(home) = dirs::home_dir() {
+                        let chev_dir = home.join(".chev");
+                        if chev_dir.exists() {
+                            // Remove everything except the bin folder?
+                            // Actually user likely wants a full state reset.
+                            let _ = std::fs::remove_file(chev_dir.join("history.txt"));
+                            let _ = std::fs::remove_file(chev_dir.join("suggestions.json"));
+                            let _ = std::fs::remove_file(chev_dir.join("macros.json"));
+
+                            writeln!(io.stdout, "{}✅ Local history, suggestions, and macros wiped.{}", teal, reset)?;
+                        }
+                    }
+                }
+                Some("build") => {
+                    writeln!(io.stdout, "{}🛠️  Internal Rebuild starting...{}", gray, reset)?;
+                    let status = std::process::Command::new("cargo")
+                        .arg("build")
+                        .status();
+
+                    if status.is_ok() && status.unwrap().success() {
+                        writeln!(io.stdout, "{}✅ Rebuild complete! Restart to apply changes.{}", teal, reset)?;
+                    } else {
+                        writeln!(io.stdout, "{}❌ Build failed.{}", red, reset)?;
+                    }
+                }
+                Some("setup") => {
+                    // Alias to ai setup logic is tricky without duplication,
+                    // so we'll just advise or eventually refactor.
+                    // For now, let's just trigger it or explain.
+                    writeln!(io.stdout, "{}Tip: Use 'ai setup' for the full model + tool onboarding.{}", gray, reset)?;
+                }
+                _ => {
+                    writeln!(io.stdout, "{}🐚 Chev Lifecycle Management:{}", teal, reset)?;
+                    writeln!(io.stdout, "  chev install   - Symlink chev to /usr/local/bin/chev")?;
+                    writeln!(io.stdout, "  chev uninstall - Wipe all tools, configs, and the link")?;
+                    writeln!(io.stdout, "  chev cleanup   - Reset history, suggestions, and isolated tools")?;
+                    writeln!(io.stdout, "  chev build     - Recompile the shell from source")?;
+                    writeln!(io.stdout, "  chev setup     - Guide for full system setup")?;
+                }
+            }
+            Ok(0)
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use anyhow::anyhow;
+use std::io::Write;
+
+pub struct Help;
+
+impl Builtin for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "help [builtin]"
+    }
+
+    fn help(&self) -> &'static str {
+        "List the builtins, or describe one"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Builtins
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let registry = ctx.builtins;
+            let teal = "\x1b[38;2;110;209;195m";
+            let gray = "\x1b[90m";
+            let reset = "\x1b[0m";
+
+            let Some(name) = args.get(1) else {
+                writeln!(io.stdout, "{}🐚 Chev Builtins:{}", teal, reset)?;
+                for builtin in registry.list() {
+                    writeln!(io.stdout, "  {:<30} {}{}{}", builtin.usage(), gray, builtin.help(), reset)?;
+                }
+                return Ok(0);
+            };
+
+            let builtin = registry.get(name).ok_or_else(|| anyhow!("help: no builtin named {}", name))?;
+            writeln!(io.stdout, "{}{}{}", teal, builtin.usage(), reset)?;
+            writeln!(io.stdout, "  {}", builtin.help())?;
+            if !builtin.aliases().is_empty() {
+                writeln!(io.stdout, "  {}Also: {}{}", gray, builtin.aliases().join(", "), reset)?;
+            }
+            if let Completion::Words(words) = builtin.completion() {
+                for (word, description) in words {
+                    writeln!(io.stdout, "  {:<12} {}{}{}", word, gray, description, reset)?;
+                }
+            }
+            Ok(0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::test_support::Shell;
+
+    #[tokio::test]
+    async fn test_help() {
+        let shell = Shell::new();
+        let help = shell.output("help").await;
+        assert_eq!(help.lines().filter(|line| line.contains("fg [job]")).count(), 1);
+        let help = shell.output("help broot").await;
+        assert!(help.contains("Open broot in a side pane") && help.contains("Also: br"));
+        assert!(shell.run("help no-such-builtin").await.is_err());
+    }
+}
@@ -0,0 +1,528 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use crate::engine::executor::{catch_signal, ignore_signal, off_runtime, quote_word, reset_signal};
+use crate::engine::jobs::{Foreground, Job, JobStatus};
+use anyhow::{anyhow, Result};
+use nix::sys::signal::{kill, killpg, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag};
+use nix::unistd::Pid;
+use std::io::Write;
+use std::str::FromStr;
+
+pub struct Jobs;
+
+impl Builtin for Jobs {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn usage(&self) -> &'static str {
+        "jobs [-l]"
+    }
+
+    fn help(&self) -> &'static str {
+        "List background and stopped jobs, with -l each process in them too"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[("-l", "Show each process's pid and state")])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let long = match args.get(1).map(String::as_str) {
+                Some("-l") => true,
+                Some(arg) => return Err(anyhow!("jobs: {}: invalid option", arg)),
+                None => false,
+            };
+            let mut jobs = ctx.jobs.lock().unwrap();
+            jobs.reap();
+            let gray = "\x1b[90m";
+            let reset = "\x1b[0m";
+
+            for job in jobs.get_jobs() {
+                let elapsed = job.start_time.elapsed();
+                let duration_str = if elapsed.as_secs() < 60 {
+                    format!("{}s", elapsed.as_secs())
+                } else if elapsed.as_secs() < 3600 {
+                    format!("{}m {}s", elapsed.as_secs() / 60, elapsed.as_secs() % 60)
+                } else {
+                    format!("{}h {}m", elapsed.as_secs() / 3600, (elapsed.as_secs() % 3600) / 60)
+                };
+
+                writeln!(
+                    io.stdout,
+                    "[{}]{} {}  \t {} \t {}(active for {}){}",
+                    job.id, jobs.marker(job.id), job.status, job.cmd, gray, duration_str, reset
+                )?;
+                if long {
+                    for member in &job.members {
+                        writeln!(io.stdout, "      {:<8}{:<24}{}", member.pid, member.status.to_string(), member.cmd)?;
+                    }
+                }
+            }
+            // What was just listed needn't be announced again
+            jobs.take_notices();
+            Ok(0)
+        })
+    }
+}
+
+pub struct Fg;
+
+impl Builtin for Fg {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "fg [job]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Bring a job, by default the current one, to the foreground"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let target_job = find_job(args, ctx)?;
+            writeln!(io.stdout, "Bringing job [{}] to foreground: {}", target_job.id, target_job.cmd)?;
+
+            // Waited on here rather than by the reaper, until it stops again
+            let mut job = ctx.jobs.lock().unwrap().take_job(target_job.id)
+                .ok_or_else(|| anyhow!("fg: %{}: no such job", target_job.id))?;
+
+            // 1. Give terminal to job, as it was when the job stopped
+            let is_tty = unsafe { libc::isatty(libc::STDIN_FILENO) != 0 };
+            let terminal = is_tty.then(|| Foreground::give(job.pgid, job.tmodes.as_ref()));
+
+            // 2. Resume if stopped
+            let _ = killpg(job.pgid, Signal::SIGCONT);
+            job.resume();
+
+            // 3. Wait for every member, or for it to stop, on a thread that may block
+            let mut job = off_runtime(move || {
+                for member in job.members.iter_mut().filter(|member| !member.status.is_finished()) {
+                    if let Ok(wait_status) = waitpid(member.pid, Some(WaitPidFlag::WUNTRACED)) {
+                        member.update(wait_status);
+                    }
+                }
+                job.update();
+                job
+            }).await?;
+
+            // 4. Take back terminal, keeping the job's modes for next time
+            let tmodes = terminal.and_then(Foreground::take_back);
+
+            let status = job.status.exit_code();
+            if let JobStatus::Stopped(_) = job.status {
+                writeln!(io.stdout, "\n[{}] {} \t Stopped", job.id, job.cmd)?;
+                job.tmodes = tmodes;
+                ctx.jobs.lock().unwrap().put_back(job);
+            } else {
+                // Lets ids start over if that was the last job
+                ctx.jobs.lock().unwrap().remove_job(job.pgid);
+            }
+            Ok(status)
+        })
+    }
+}
+
+pub struct Bg;
+
+impl Builtin for Bg {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "bg [job]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Resume a stopped job, by default the current one, in the background"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let target_job = find_job(args, ctx)?;
+            writeln!(io.stdout, "Resuming job [{}] in background: {}", target_job.id, target_job.cmd)?;
+            let _ = killpg(target_job.pgid, Signal::SIGCONT);
+            if let Some(job) = ctx.jobs.lock().unwrap().find_job_by_id_mut(target_job.id) {
+                job.resume();
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Kill;
+
+impl Builtin for Kill {
+    fn name(&self) -> &'static str {
+        "kill"
+    }
+
+    fn usage(&self) -> &'static str {
+        "kill [-SIGNAL] <pid|job>..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Send a signal, TERM unless given, to processes or jobs"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("-TERM", "Ask it to exit (the default)"),
+            ("-KILL", "Kill it outright"),
+            ("-HUP", "Hang up"),
+            ("-INT", "Interrupt, like Ctrl+C"),
+            ("-STOP", "Stop it, like Ctrl+Z"),
+            ("-CONT", "Resume it if stopped"),
+            ("-l", "List signal names"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let usage = || anyhow!("kill: usage: kill [-s SIGNAL | -SIGNAL] <pid|job>... or kill -l [SIGNAL]");
+            let (signal, targets) = match args.get(1).map(String::as_str) {
+                Some("-l" | "-L") => return list_signals(&args[2..], io),
+                Some("-s" | "-n") => (parse_signal(args.get(2).ok_or_else(usage)?)?, &args[3..]),
+                Some("--") => (Signal::SIGTERM, &args[2..]),
+                Some(flag) if flag.len() > 1 && flag.starts_with('-') => (parse_signal(&flag[1..])?, &args[2..]),
+                _ => (Signal::SIGTERM, &args[1..]),
+            };
+            if targets.is_empty() {
+                return Err(usage());
+            }
+
+            let mut status = 0;
+            for target in targets {
+                if let Err(e) = send_signal(target, signal, ctx) {
+                    writeln!(io.stderr, "kill: {}", e)?;
+                    status = 1;
+                }
+            }
+            Ok(status)
+        })
+    }
+}
+
+fn send_signal(target: &str, signal: Signal, ctx: Context<'_>) -> Result<()> {
+    if target.starts_with('%') {
+        let job = ctx.jobs.lock().unwrap().find_job(target)?.clone();
+        killpg(job.pgid, signal).map_err(|e| anyhow!("{}: {}", target, e.desc()))?;
+        // A stopped job can't act on it until it's resumed
+        if matches!(job.status, JobStatus::Stopped(_)) && matches!(signal, Signal::SIGTERM | Signal::SIGHUP) {
+            let _ = killpg(job.pgid, Signal::SIGCONT);
+        }
+    } else {
+        let pid = target.parse::<i32>().map_err(|_| anyhow!("{}: arguments must be process or job IDs", target))?;
+        kill(Pid::from_raw(pid), signal).map_err(|e| anyhow!("({}) - {}", pid, e.desc()))?;
+    }
+    Ok(())
+}
+
+/// `kill -l`: every signal, or the name for each number (or exit status) given and vice versa.
+fn list_signals(names: &[String], io: &mut BuiltinIo) -> Result<i32> {
+    if names.is_empty() {
+        let signals: Vec<String> = Signal::iterator().map(|signal| format!("{:>2}) {:<12}", signal as i32, signal.as_str())).collect();
+        for row in signals.chunks(5) {
+            writeln!(io.stdout, "{}", row.concat().trim_end())?;
+        }
+        return Ok(0);
+    }
+    for name in names {
+        match name.parse::<i32>() {
+            Ok(number) => {
+                let number = if number > 128 { number - 128 } else { number };
+                let signal = Signal::try_from(number).map_err(|_| anyhow!("kill: {}: invalid signal specification", name))?;
+                writeln!(io.stdout, "{}", &signal.as_str()[3..])?;
+            }
+            Err(_) => writeln!(io.stdout, "{}", parse_signal(name)? as i32)?,
+        }
+    }
+    Ok(0)
+}
+
+/// A signal by number or by name, with or without the `SIG` prefix: `9`, `KILL`, `sigkill`.
+fn parse_signal(spec: &str) -> Result<Signal> {
+    let invalid = || anyhow!("kill: {}: invalid signal specification", spec);
+    if let Ok(number) = spec.parse::<i32>() {
+        return Signal::try_from(number).map_err(|_| invalid());
+    }
+    let name = spec.to_ascii_uppercase();
+    let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+    Signal::from_str(&name).map_err(|_| invalid())
+}
+
+pub struct Wait;
+
+impl Builtin for Wait {
+    fn name(&self) -> &'static str {
+        "wait"
+    }
+
+    fn usage(&self) -> &'static str {
+        "wait [-n] [pid|job]..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Wait for jobs, or with -n the next one, to finish"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[("-n", "Return when the next job finishes")])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let (next, specs) = match args.get(1).map(String::as_str) {
+                Some("-n") => (true, &args[2..]),
+                _ => (false, &args[1..]),
+            };
+
+            let mut targets = Vec::new();
+            {
+                let jobs = ctx.jobs.lock().unwrap();
+                for spec in specs {
+                    let job = match spec.parse::<i32>() {
+                        Ok(pid) => jobs.get_jobs().iter()
+                            .find(|job| !job.inherited && job.members.iter().any(|member| member.pid.as_raw() == pid))
+                            .ok_or_else(|| anyhow!("wait: pid {} is not a child of this shell", pid))?,
+                        Err(_) => jobs.find_job(spec).map_err(|e| anyhow!("wait: {}", e))?,
+                    };
+                    // A subshell can see its parent's jobs but not wait for them
+                    if job.inherited {
+                        return Err(anyhow!("wait: {}: not a child of this shell", spec));
+                    }
+                    targets.push(job.pgid);
+                }
+                if specs.is_empty() {
+                    targets.extend(jobs.get_jobs().iter().filter(|job| !job.inherited).map(|job| job.pgid));
+                }
+            }
+            if targets.is_empty() {
+                // Nothing to wait for; `wait -n` says so like bash does
+                return Ok(if next { 127 } else { 0 });
+            }
+
+            let reaped = ctx.jobs.lock().unwrap().reaped();
+            let signalled = ctx.env.lock().unwrap().signalled();
+            loop {
+                // Listen before looking, so a reap or signal in between isn't missed
+                let on_reap = reaped.notified();
+                let on_signal = signalled.notified();
+                tokio::pin!(on_reap, on_signal);
+                on_signal.as_mut().enable();
+                // A caught signal cuts the wait short so its trap can run
+                if let Some(signal) = ctx.env.lock().unwrap().pending_signal() {
+                    return Ok(128 + signal);
+                }
+                {
+                    let mut jobs = ctx.jobs.lock().unwrap();
+                    jobs.reap();
+                    on_reap.as_mut().enable();
+                    // Jobs that have finished or stopped, in the order they were asked for
+                    let settled: Vec<Option<Job>> = targets.iter()
+                        .map(|&pgid| jobs.get_jobs().iter().find(|job| job.pgid == pgid).cloned())
+                        .filter(|job| job.as_ref().is_none_or(|job| job.status != JobStatus::Running))
+                        .collect();
+                    if (next && !settled.is_empty()) || settled.len() == targets.len() {
+                        let chosen = if next { &settled[..1] } else { &settled[..] };
+                        for job in chosen.iter().flatten().filter(|job| job.status.is_finished()) {
+                            jobs.remove_job(job.pgid);
+                        }
+                        // A job that was already reaped and reported counts as a success
+                        return Ok(chosen.last().cloned().flatten().map_or(0, |job| job.status.exit_code()));
+                    }
+                }
+                tokio::select! {
+                    _ = on_reap => {}
+                    _ = on_signal => {}
+                }
+            }
+        })
+    }
+}
+
+pub struct Disown;
+
+impl Builtin for Disown {
+    fn name(&self) -> &'static str {
+        "disown"
+    }
+
+    fn usage(&self) -> &'static str {
+        "disown [-a] [job]..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Stop tracking jobs, by default the current one, so they're never reported"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[("-a", "Disown every job")])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let mut jobs = ctx.jobs.lock().unwrap();
+            let ids: Vec<usize> = match args.get(1).map(String::as_str) {
+                Some("-a") => jobs.get_jobs().iter().map(|job| job.id).collect(),
+                None => vec![jobs.find_job("%+").map_err(|_| anyhow!("disown: no current job"))?.id],
+                Some(_) => args[1..].iter()
+                    .map(|spec| jobs.find_job(spec).map(|job| job.id).map_err(|e| anyhow!("disown: {}", e)))
+                    .collect::<Result<_>>()?,
+            };
+            for id in ids {
+                jobs.disown(id);
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Trap;
+
+impl Builtin for Trap {
+    fn name(&self) -> &'static str {
+        "trap"
+    }
+
+    fn usage(&self) -> &'static str {
+        "trap [-lp] [command] [signal]..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Run a command when the shell gets a signal or, for EXIT, as it exits"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("-p", "List the traps that are set"),
+            ("-l", "List signal names"),
+            ("-", "Reset signals to their defaults"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let rest = match args.get(1).map(String::as_str) {
+                Some("-l") => return list_signals(&args[2..], io),
+                Some("-p") => return print_traps(&args[2..], io, ctx),
+                Some("--") => &args[2..],
+                _ => &args[1..],
+            };
+            let (command, specs) = match rest {
+                [] => return print_traps(&[], io, ctx),
+                // A lone signal goes back to its default, as with `trap - SIGNAL`
+                [spec] if trap_signal(spec).is_ok() => (None, rest),
+                [_] => return Err(anyhow!("trap: usage: trap [-lp] [[command] signal...]")),
+                [command, specs @ ..] => ((command != "-").then(|| command.clone()), specs),
+            };
+            let signals = specs.iter().map(|spec| trap_signal(spec)).collect::<Result<Vec<_>>>()?;
+            for signal in signals {
+                // EXIT isn't a real signal; the shell runs it on its way out
+                let handled = match command.as_deref() {
+                    _ if signal == 0 => Ok(()),
+                    Some("") => ignore_signal(signal, ctx.env),
+                    Some(_) => catch_signal(signal, ctx.env),
+                    None => reset_signal(signal, ctx.env),
+                };
+                handled.map_err(|e| anyhow!("trap: {}: {}", signal_name(signal), e))?;
+                ctx.env.lock().unwrap().set_trap(signal, command.clone());
+            }
+            Ok(0)
+        })
+    }
+}
+
+/// `trap -p`: the traps for the given signals, or all of them, in a form that sets them again.
+fn print_traps(specs: &[String], io: &mut BuiltinIo, ctx: Context<'_>) -> Result<i32> {
+    let signals = specs.iter().map(|spec| trap_signal(spec)).collect::<Result<Vec<_>>>()?;
+    let env = ctx.env.lock().unwrap();
+    for (signal, command) in env.traps().filter(|(signal, _)| signals.is_empty() || signals.contains(signal)) {
+        writeln!(io.stdout, "trap -- {} {}", quote_word(command), signal_name(signal))?;
+    }
+    Ok(0)
+}
+
+/// A signal as `trap` takes it: like `kill`, plus `EXIT` (or 0) for the shell exiting.
+fn trap_signal(spec: &str) -> Result<i32> {
+    if spec == "0" || spec.eq_ignore_ascii_case("EXIT") {
+        return Ok(0);
+    }
+    parse_signal(spec).map(|signal| signal as i32).map_err(|_| anyhow!("trap: {}: invalid signal specification", spec))
+}
+
+fn signal_name(signal: i32) -> String {
+    match signal {
+        0 => "EXIT".to_string(),
+        _ => Signal::try_from(signal).map_or_else(|_| signal.to_string(), |signal| signal.as_str().to_string()),
+    }
+}
+
+/// The job `fg` or `bg` was given, or the current one.
+fn find_job(args: &[String], ctx: Context<'_>) -> Result<Job> {
+    let spec = args.get(1).map_or("%+", String::as_str);
+    let jobs = ctx.jobs.lock().unwrap();
+    let job = jobs.find_job(spec).map_err(|e| match args.get(1) {
+        Some(_) => anyhow!("{}: {}", args[0], e),
+        None => anyhow!("{}: no current job", args[0]),
+    })?;
+    Ok(job.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::jobs::JobStatus;
+    use crate::engine::test_support::Shell;
+
+    #[tokio::test]
+    async fn test_kill_and_wait() {
+        let shell = Shell::new();
+        let script = "sleep 10 & sh -c 'exit 3' & sleep 0.1 &\n\
+                      kill %sleep\\ 10; wait %1; echo $?\n\
+                      wait %?exit; echo $?\n\
+                      wait -n; echo $?\n\
+                      wait -n; echo $?";
+        // Killed by TERM, then each job's own status, then nothing left to wait for
+        assert_eq!(shell.output(script).await, "143\n3\n0\n127");
+        assert!(shell.jobs.lock().unwrap().get_jobs().is_empty());
+        assert!(shell.run("kill %1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_jobs_long() {
+        let shell = Shell::new();
+        shell.run("sleep 0.1 | sleep 10 &").await.unwrap();
+        // Wait for the first member itself to be reaped rather than for long enough
+        let reaped = shell.jobs.lock().unwrap().reaped();
+        loop {
+            let on_reap = reaped.notified();
+            tokio::pin!(on_reap);
+            on_reap.as_mut().enable();
+            if shell.jobs.lock().unwrap().get_jobs()[0].members[0].status != JobStatus::Running {
+                break;
+            }
+            on_reap.await;
+        }
+        let listing = shell.output("jobs -l").await;
+        shell.run("kill %1; wait").await.ok();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert!(lines[0].starts_with("[1]+ Running"));
+        // Each member on its own line, the first already reaped while the second runs on
+        assert!(lines[1].contains("Done") && lines[1].ends_with("sleep 0.1"));
+        assert!(lines[2].contains("Running") && lines[2].ends_with("sleep 10"));
+        assert!(shell.jobs.lock().unwrap().get_jobs().is_empty());
+    }
+}
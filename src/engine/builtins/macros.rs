@@ -0,0 +1,59 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use std::io::Write;
+
+pub struct Macro;
+
+impl Builtin for Macro {
+    fn name(&self) -> &'static str {
+        "macro"
+    }
+
+    fn usage(&self) -> &'static str {
+        "macro [set <name> <template>|unset <name>]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Define, remove or list macros"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("set", "Define a macro"),
+            ("unset", "Remove a macro"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let mut macros = ctx.macros.lock().unwrap();
+            let teal = "\x1b[38;2;110;209;195m";
+            let reset = "\x1b[0m";
+
+            match args.get(1).map(|s| s.as_str()) {
+                Some("set") => {
+                    if args.len() >= 4 {
+                        let name = args[2].clone();
+                        let template = args[3..].join(" ");
+                        macros.set_macro(name, template)?;
+                        writeln!(io.stdout, "Macro set successfully.")?;
+                    } else {
+                        writeln!(io.stdout, "Usage: macro set <name> <template>")?;
+                    }
+                }
+                Some("unset") => {
+                    if let Some(name) = args.get(2) {
+                        macros.unset_macro(name)?;
+                        writeln!(io.stdout, "Macro unset.")?;
+                    }
+                }
+                _ => {
+                    writeln!(io.stdout, "{}🐚 Chev Macros:{}", teal, reset)?;
+                    for (name, m) in macros.list() {
+                        writeln!(io.stdout, "  {} -> {}", name, m.template)?;
+                    }
+                }
+            }
+            Ok(0)
+        })
+    }
+}
@@ -0,0 +1,224 @@
+use crate::engine::env::EnvManager;
+use crate::engine::jobs::JobManager;
+use crate::engine::macros::MacroManager;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::future::Future;
+use std::io::{LineWriter, Read, Write};
+use std::os::fd::OwnedFd;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+mod ai;
+mod chev;
+mod help;
+mod jobs;
+mod macros;
+mod paths;
+mod rio;
+mod vars;
+
+/// A builtin's standard streams. They are whatever the stage's stdin, stdout and stderr
+/// point at once pipes and redirections are in place.
+pub struct BuiltinIo {
+    pub stdin: Box<dyn Read + Send>,
+    pub stdout: Box<dyn Write + Send>,
+    pub stderr: Box<dyn Write + Send>,
+}
+
+impl BuiltinIo {
+    /// Streams over the given descriptors, using the shell's own for any that are `None`.
+    pub fn new(stdin: Option<&OwnedFd>, stdout: Option<&OwnedFd>, stderr: Option<&OwnedFd>) -> Result<Self> {
+        Ok(Self {
+            stdin: match stdin {
+                Some(fd) => Box::new(File::from(fd.try_clone()?)),
+                None => Box::new(std::io::stdin()),
+            },
+            stdout: match stdout {
+                Some(fd) => Box::new(LineWriter::new(File::from(fd.try_clone()?))),
+                None => Box::new(std::io::stdout()),
+            },
+            stderr: match stderr {
+                Some(fd) => Box::new(File::from(fd.try_clone()?)),
+                None => Box::new(std::io::stderr()),
+            },
+        })
+    }
+}
+
+/// The shell state a builtin can see and change.
+#[derive(Clone, Copy)]
+pub struct Context<'a> {
+    pub jobs: &'a Arc<Mutex<JobManager>>,
+    pub env: &'a Arc<Mutex<EnvManager>>,
+    pub macros: &'a Arc<Mutex<MacroManager>>,
+    pub builtins: &'a Arc<BuiltinRegistry>,
+}
+
+pub type BuiltinFuture<'a> = Pin<Box<dyn Future<Output = Result<i32>> + Send + 'a>>;
+
+/// What tab completion offers for a builtin's first argument.
+#[derive(Clone, Copy)]
+pub enum Completion {
+    /// File and directory names, as for a program
+    Paths,
+    /// A fixed set of words, each with a short description
+    Words(&'static [(&'static str, &'static str)]),
+    /// The names of builtins, as for `help`
+    Builtins,
+    /// Nothing worth offering
+    Nothing,
+}
+
+/// A command the shell runs itself rather than spawning.
+pub trait Builtin: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// How to call it, e.g. `fg <id>`.
+    fn usage(&self) -> &'static str;
+
+    /// One line on what it does, for `help` and completion.
+    fn help(&self) -> &'static str;
+
+    /// Other names it answers to.
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Paths
+    }
+
+    /// Runs with `args[0]` as the name it was called by, returning its exit status.
+    /// An error fails the command with status 1 after being printed to `io.stderr`.
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a>;
+}
+
+/// Every builtin the shell knows, by name and alias. It starts out with chev's own,
+/// and an embedder can `register` more or replace them.
+#[derive(Clone)]
+pub struct BuiltinRegistry {
+    builtins: BTreeMap<&'static str, Arc<dyn Builtin>>,
+}
+
+impl BuiltinRegistry {
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Arc::new(jobs::Jobs));
+        registry.register(Arc::new(jobs::Fg));
+        registry.register(Arc::new(jobs::Bg));
+        registry.register(Arc::new(jobs::Kill));
+        registry.register(Arc::new(jobs::Wait));
+        registry.register(Arc::new(jobs::Disown));
+        registry.register(Arc::new(jobs::Trap));
+        registry.register(Arc::new(vars::Set));
+        registry.register(Arc::new(vars::Export));
+        registry.register(Arc::new(vars::Unset));
+        registry.register(Arc::new(vars::Local));
+        registry.register(Arc::new(vars::Return));
+        registry.register(Arc::new(vars::Let));
+        registry.register(Arc::new(vars::Math));
+        registry.register(Arc::new(paths::Cd));
+        registry.register(Arc::new(paths::Path));
+        registry.register(Arc::new(paths::Pushd));
+        registry.register(Arc::new(paths::Popd));
+        registry.register(Arc::new(paths::Dirs));
+        registry.register(Arc::new(macros::Macro));
+        registry.register(Arc::new(ai::Ai));
+        registry.register(Arc::new(chev::Chev));
+        registry.register(Arc::new(help::Help));
+        registry.register(Arc::new(rio::Rio));
+        registry.register(Arc::new(rio::Preview));
+        registry.register(Arc::new(rio::Minimap));
+        registry.register(Arc::new(rio::Effect));
+        registry.register(Arc::new(rio::Progress));
+        registry.register(Arc::new(rio::Vibe));
+        registry.register(Arc::new(rio::History));
+        registry.register(Arc::new(rio::Voice));
+        registry.register(Arc::new(rio::Browse));
+        registry.register(Arc::new(rio::Broot));
+        registry
+    }
+
+    /// A registry with no builtins at all.
+    pub fn empty() -> Self {
+        Self { builtins: BTreeMap::new() }
+    }
+
+    /// Adds `builtin` under its name and aliases, replacing whatever had them before.
+    pub fn register(&mut self, builtin: Arc<dyn Builtin>) {
+        for &name in std::iter::once(&builtin.name()).chain(builtin.aliases()) {
+            self.builtins.insert(name, Arc::clone(&builtin));
+        }
+    }
+
+    /// The builtin called `name`, by its own name or an alias.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Builtin>> {
+        self.builtins.get(name).cloned()
+    }
+
+    /// Each builtin once, sorted by name.
+    pub fn list(&self) -> impl Iterator<Item = &Arc<dyn Builtin>> {
+        self.builtins.iter().filter(|(name, builtin)| **name == builtin.name()).map(|(_, builtin)| builtin)
+    }
+
+    /// Every name a builtin answers to, aliases included, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.builtins.keys().copied()
+    }
+}
+
+impl Default for BuiltinRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Hello;
+
+    impl Builtin for Hello {
+        fn name(&self) -> &'static str {
+            "hello"
+        }
+
+        fn usage(&self) -> &'static str {
+            "hello"
+        }
+
+        fn help(&self) -> &'static str {
+            "Say hello"
+        }
+
+        fn aliases(&self) -> &'static [&'static str] {
+            &["hi", "jobs"]
+        }
+
+        fn run<'a>(&'a self, _args: &'a [String], io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+            Box::pin(async move {
+                writeln!(io.stdout, "hello")?;
+                Ok(0)
+            })
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = BuiltinRegistry::new();
+        assert_eq!(registry.get("br").unwrap().name(), "broot");
+        assert_eq!(registry.get("music").unwrap().name(), "vibe");
+        assert!(registry.get("ls").is_none());
+        // Aliases answer to their names but are listed once
+        assert!(registry.list().all(|builtin| builtin.name() != "br"));
+        assert!(registry.names().any(|name| name == "br"));
+
+        registry.register(Arc::new(Hello));
+        assert_eq!(registry.get("hi").unwrap().name(), "hello");
+        assert_eq!(registry.get("jobs").unwrap().name(), "hello");
+        assert_eq!(registry.list().filter(|builtin| builtin.name() == "hello").count(), 1);
+    }
+}
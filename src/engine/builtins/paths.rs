@@ -0,0 +1,216 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use crate::engine::env::EnvManager;
+use anyhow::{Result, anyhow};
+use std::io::Write;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::process::Command;
+
+pub struct Cd;
+
+impl Builtin for Cd {
+    fn name(&self) -> &'static str {
+        "cd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "cd [dir]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Change directory, jumping with zoxide when it isn't a path"
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            handle_cd(args.iter().skip(1).map(|s| s.as_str()).collect(), ctx.env).await?;
+            Ok(0)
+        })
+    }
+}
+
+async fn handle_cd(args: Vec<&str>, env_mutex: &Arc<Mutex<EnvManager>>) -> Result<()> {
+    let target = args.first().copied().unwrap_or("~");
+
+    // Resolve path (handle ~)
+    let path_str = if target == "~" {
+        dirs::home_dir()
+            .ok_or_else(|| anyhow!("Could not find home directory"))?
+            .to_string_lossy()
+            .to_string()
+    } else if let Some(rest) = target.strip_prefix("~/") {
+        let home = dirs::home_dir()
+            .ok_or_else(|| anyhow!("Could not find home directory"))?;
+        home.join(rest).to_string_lossy().to_string()
+    } else {
+        target.to_string()
+    };
+
+    let path = FsPath::new(&path_str);
+
+    // 1. Try direct cd
+    if path.exists() && path.is_dir() {
+        std::env::set_current_dir(path)
+            .map_err(|e| anyhow!("cd failed: {}", e))?;
+        // Update zoxide history
+        let _ = Command::new("zoxide").arg("add").arg(path).spawn()?.wait().await;
+        return Ok(());
+    }
+
+    // 2. Smart jump via zoxide if target is not a valid path
+    let mut zoxide_cmd = Command::new("zoxide");
+    zoxide_cmd.arg("query").arg(target);
+
+    // zoxide sees the same environment as any other child
+    zoxide_cmd.env_clear();
+    zoxide_cmd.envs(env_mutex.lock().unwrap().exported_vars());
+
+    let output = zoxide_cmd.output().await?;
+
+    if output.status.success() {
+        let new_path_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !new_path_str.is_empty() {
+            let new_path = FsPath::new(&new_path_str);
+            if new_path.exists() {
+                std::env::set_current_dir(new_path)
+                    .map_err(|e| anyhow!("cd failed: {}", e))?;
+                // Confirm visit to zoxide
+                let _ = Command::new("zoxide").arg("add").arg(new_path).spawn()?.wait().await;
+                return Ok(());
+            }
+        }
+    }
+
+    Err(anyhow!("cd: no such file or directory: {}", target))
+}
+
+pub struct Path;
+
+impl Builtin for Path {
+    fn name(&self) -> &'static str {
+        "path"
+    }
+
+    fn usage(&self) -> &'static str {
+        "path add|prepend <dir>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Add a directory to the end or front of $PATH"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("add", "Append a directory to $PATH"),
+            ("prepend", "Put a directory first in $PATH"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            // Modern helper: path add /path/to/bin
+            let mut env = ctx.env.lock().unwrap();
+            match args.get(1).map(|s| s.as_str()) {
+                Some("add") => {
+                    if let Some(p) = args.get(2) {
+                        env.add_to_path(p, false);
+                        writeln!(io.stdout, "Added to PATH: {}", p)?;
+                    }
+                }
+                Some("prepend") => {
+                    if let Some(p) = args.get(2) {
+                        env.add_to_path(p, true);
+                        writeln!(io.stdout, "Prepended to PATH: {}", p)?;
+                    }
+                }
+                _ => {
+                    writeln!(io.stdout, "Usage: path [add|prepend] <dir>")?;
+                }
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Pushd;
+
+impl Builtin for Pushd {
+    fn name(&self) -> &'static str {
+        "pushd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "pushd <dir>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Save the current directory and change to another"
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let mut env = ctx.env.lock().unwrap();
+            if let Some(p) = args.get(1) {
+                env.pushd(PathBuf::from(p))?;
+                writeln!(io.stdout, "{}", env.get_stack().join("  "))?;
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Popd;
+
+impl Builtin for Popd {
+    fn name(&self) -> &'static str {
+        "popd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "popd"
+    }
+
+    fn help(&self) -> &'static str {
+        "Return to the last directory saved by pushd"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, _args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let mut env = ctx.env.lock().unwrap();
+            env.popd()?;
+            writeln!(io.stdout, "{}", env.get_stack().join("  "))?;
+            Ok(0)
+        })
+    }
+}
+
+pub struct Dirs;
+
+impl Builtin for Dirs {
+    fn name(&self) -> &'static str {
+        "dirs"
+    }
+
+    fn usage(&self) -> &'static str {
+        "dirs"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show the directory stack"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, _args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            writeln!(io.stdout, "{}", ctx.env.lock().unwrap().get_stack().join("  "))?;
+            Ok(0)
+        })
+    }
+}
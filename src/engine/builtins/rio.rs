@@ -0,0 +1,387 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use anyhow::anyhow;
+use std::io::Write;
+
+const ON_OFF: &[(&str, &str)] = &[("on", "Turn it on"), ("off", "Turn it off")];
+
+pub struct Rio;
+
+impl Builtin for Rio {
+    fn name(&self) -> &'static str {
+        "rio"
+    }
+
+    fn usage(&self) -> &'static str {
+        "rio <command>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Control the Rio terminal"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("notify", "Send a system notification"),
+            ("opacity", "Change terminal transparency"),
+            ("badge", "Set the tab badge"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let teal = "\x1b[38;2;110;209;195m";
+            let reset = "\x1b[0m";
+            let gray = "\x1b[90m";
+
+            match args.get(1).map(|s| s.as_str()) {
+                Some("notify") => {
+                    if args.len() >= 4 {
+                        let title = args[2].clone();
+                        let msg = args[3..].join(" ");
+                        crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::Notify { title, message: msg });
+                    } else {
+                        writeln!(io.stdout, "Usage: rio notify <title> <message>")?;
+                    }
+                }
+                Some("opacity") => {
+                    if let Some(val_str) = args.get(2) {
+                        if let Ok(val) = val_str.parse::<f32>() {
+                            crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::Opacity(val));
+                        }
+                    }
+                }
+                Some("badge") => {
+                    let text = args[2..].join(" ");
+                    crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::Badge(text));
+                }
+                _ => {
+                    writeln!(io.stdout, "{}🌊 Rio Terminal Control:{}", teal, reset)?;
+                    writeln!(io.stdout, "  {}rio notify <title> <msg>{} - Send system notification", gray, reset)?;
+                    writeln!(io.stdout, "  {}rio opacity <0.0-1.0>{}   - Change terminal transparency", gray, reset)?;
+                    writeln!(io.stdout, "  {}rio badge <text>{}       - Set tab badge", gray, reset)?;
+                }
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Preview;
+
+impl Builtin for Preview {
+    fn name(&self) -> &'static str {
+        "preview"
+    }
+
+    fn usage(&self) -> &'static str {
+        "preview <file>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show a file in Rio's preview pane"
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            match args.get(1) {
+                Some(path) => crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::Preview(path.clone())),
+                None => writeln!(io.stdout, "Usage: preview <file>")?,
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Minimap;
+
+impl Builtin for Minimap {
+    fn name(&self) -> &'static str {
+        "minimap"
+    }
+
+    fn usage(&self) -> &'static str {
+        "minimap [on|off]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show or hide Rio's minimap"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(ON_OFF)
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let enabled = !matches!(args.get(1).map(|s| s.as_str()), Some("off") | Some("0") | Some("hide"));
+            crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::MiniMap(enabled));
+            Ok(0)
+        })
+    }
+}
+
+pub struct Effect;
+
+impl Builtin for Effect {
+    fn name(&self) -> &'static str {
+        "effect"
+    }
+
+    fn usage(&self) -> &'static str {
+        "effect <matrix|vibe|none>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Set Rio's background effect"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("matrix", "Falling code"),
+            ("vibe", "Audio-reactive visuals"),
+            ("none", "No effect"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let effect = match args.get(1).map(|s| s.as_str()) {
+                Some("off") | Some("none") | Some("0") => None,
+                Some(s) => Some(s.to_string()),
+                None => {
+                    writeln!(io.stdout, "Usage: effect <matrix|vibe|none>")?;
+                    return Ok(0);
+                }
+            };
+            crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::BackgroundEffect(effect));
+            Ok(0)
+        })
+    }
+}
+
+pub struct Progress;
+
+impl Builtin for Progress {
+    fn name(&self) -> &'static str {
+        "progress"
+    }
+
+    fn usage(&self) -> &'static str {
+        "progress <fraction> [label] | progress off"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show a progress bar in Rio"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[("off", "Hide the progress bar")])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            if args.get(1).map(|s| s.as_str()) == Some("off") {
+                crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::ProgressBar { fraction: -1.0, label: String::new() });
+            } else {
+                let fraction = args.get(1).and_then(|s| s.parse::<f32>().ok()).unwrap_or(0.0);
+                let label = args.get(2).cloned().unwrap_or_default();
+                crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::ProgressBar { fraction, label });
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Vibe;
+
+impl Builtin for Vibe {
+    fn name(&self) -> &'static str {
+        "vibe"
+    }
+
+    fn usage(&self) -> &'static str {
+        "vibe [on|off]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Sync Rio's background with the music that's playing"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["spotify", "music"]
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(ON_OFF)
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let on = !matches!(args.get(1).map(|s| s.as_str()), Some("off") | Some("0"));
+
+            if on && cfg!(not(feature = "vibe")) {
+                return Err(anyhow!("vibe: chev was built without audio input (the vibe feature)"));
+            }
+            if on {
+                writeln!(io.stdout, "\x1b[38;2;110;209;195m🐕 Vibe Mode: ON. Syncing audio with Rio...\x1b[0m")?;
+                crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::BackgroundEffect(Some("vibe".to_string())));
+                #[cfg(feature = "vibe")]
+                crate::ui::vibe::start_vibe_engine();
+            } else {
+                writeln!(io.stdout, "\x1b[90m🐕 Vibe Mode: OFF.\x1b[0m")?;
+                crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::BackgroundEffect(None));
+                // Stopping the engine is harder in this leaked setup, but we'll at least hide the effect
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct History;
+
+impl Builtin for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn usage(&self) -> &'static str {
+        "history [on|off]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show or hide Rio's command history panel"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(ON_OFF)
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let on = !matches!(args.get(1).map(|s| s.as_str()), Some("off") | Some("0"));
+            crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::ToggleHistory(on));
+            Ok(0)
+        })
+    }
+}
+
+pub struct Voice;
+
+impl Builtin for Voice {
+    fn name(&self) -> &'static str {
+        "voice"
+    }
+
+    fn usage(&self) -> &'static str {
+        "voice setup"
+    }
+
+    fn help(&self) -> &'static str {
+        "Set up Rio voice control"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[("setup", "Download the speech model")])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            if args.get(1).map(|s| s.as_str()) == Some("setup") {
+                writeln!(io.stdout, "🎙️  Setting up Rio Voice Control...")?;
+                writeln!(io.stdout, "Model: Whisper Large v3-turbo (Metal Accelerated)")?;
+                let home = std::env::var("HOME").unwrap_or_default();
+                let models_dir = format!("{}/.rio/models", home);
+
+                // Create directory
+                let _ = std::fs::create_dir_all(&models_dir);
+
+                writeln!(io.stdout, "1. Download the model file (approx 1.5GB):")?;
+                writeln!(io.stdout, "   curl -L https://huggingface.co/distil-whisper/distil-large-v3/resolve/main/ggml-large-v3-turbo.bin -o ~/.rio/models/ggml-large-v3-turbo.bin")?;
+                writeln!(io.stdout, "\n2. Once downloaded, hold the 'Fn' key in Rio to speak!")?;
+            } else {
+                writeln!(io.stdout, "Usage: voice setup")?;
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Browse;
+
+impl Builtin for Browse {
+    fn name(&self) -> &'static str {
+        "browse"
+    }
+
+    fn usage(&self) -> &'static str {
+        "browse <url>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Browse and summarize a webpage in the sidebar"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let Some(url) = args.get(1) else {
+                writeln!(io.stdout, "Usage: browse <url>")?;
+                return Ok(0);
+            };
+            let chev_path = std::env::current_exe()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| "chev".to_string());
+
+            writeln!(io.stdout, "\x1b[90m🐕 Opening AI Browser for {}...\x1b[0m", url)?;
+            crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::SplitPane {
+                direction: "right".to_string(),
+                ratio: 0.3,
+                command: format!("{} internal browse {}", chev_path, url)
+            });
+            Ok(0)
+        })
+    }
+}
+
+pub struct Broot;
+
+impl Builtin for Broot {
+    fn name(&self) -> &'static str {
+        "broot"
+    }
+
+    fn usage(&self) -> &'static str {
+        "broot"
+    }
+
+    fn help(&self) -> &'static str {
+        "Open broot in a side pane"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["br"]
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, _args: &'a [String], _io: &'a mut BuiltinIo, _ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let chev_path = std::env::current_exe()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| "chev".to_string());
+
+            // Send signal to split pane 20/80
+            crate::ui::protocol::send_rio(crate::ui::protocol::RioAction::SplitPane {
+                direction: "left".to_string(),
+                ratio: 0.2,
+                command: format!("{} internal ide-broot", chev_path)
+            });
+            Ok(0)
+        })
+    }
+}
@@ -0,0 +1,301 @@
+use super::{Builtin, BuiltinFuture, BuiltinIo, Completion, Context};
+use crate::engine::arith;
+use crate::engine::executor::{quote_word, ReturnSignal};
+use anyhow::anyhow;
+use std::io::Write;
+
+pub struct Set;
+
+impl Builtin for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn usage(&self) -> &'static str {
+        "set [-o|+o name] [-ex] [NAME VALUE]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Set and export a variable, toggle shell options, or list variables"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("-o", "Turn a shell option on, or list them"),
+            ("+o", "Turn a shell option off"),
+            ("-e", "Stop at the first failing command"),
+            ("-x", "Print each command before running it"),
+            ("--", "Replace $1..$N"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let mut env = ctx.env.lock().unwrap();
+            if args.len() == 1 {
+                // List variables with nice teal highlights
+                let teal = "\x1b[38;2;110;209;195m";
+                let reset = "\x1b[0m";
+                for (k, v) in env.get_all_vars() {
+                    writeln!(io.stdout, "{}{}={} {}", teal, k, reset, v)?;
+                }
+            } else if args[1] == "-o" || args[1] == "+o" {
+                let enabled = args[1] == "-o";
+                match args.get(2) {
+                    Some(name) => env.set_option(name, enabled)?,
+                    None => {
+                        for name in crate::engine::env::SHELL_OPTIONS {
+                            writeln!(io.stdout, "{:<12}{}", name, if env.option(name) { "on" } else { "off" })?;
+                        }
+                    }
+                }
+            } else if args[1] == "--" {
+                env.set_positional(args[2..].to_vec());
+            } else if let Some(flags) = args[1].strip_prefix(['-', '+']) && !flags.is_empty() && flags.chars().all(|c| "ex".contains(c)) {
+                // Short forms: set -e, set +x, set -ex, ...
+                for flag in flags.chars() {
+                    let name = if flag == 'e' { "errexit" } else { "xtrace" };
+                    env.set_option(name, args[1].starts_with('-'))?;
+                }
+            } else if let Some(arg) = args.get(1) {
+                // Like `export`, since these are meant as environment variables
+                if let Some((k, v)) = arg.split_once('=') {
+                    env.set_var(k.to_string(), v.to_string());
+                    env.export(k);
+                } else {
+                    // Classic style: set KEY VALUE
+                    if let Some(v) = args.get(2) {
+                        env.set_var(arg.to_string(), v.to_string());
+                    } else {
+                        // set KEY (empty value)
+                        env.set_var(arg.to_string(), "".to_string());
+                    }
+                    env.export(arg);
+                }
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Export;
+
+impl Builtin for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn usage(&self) -> &'static str {
+        "export [-n] [NAME[=VALUE]...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Pass variables to programs, or list the exported ones"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[
+            ("-n", "Stop exporting the names"),
+            ("-p", "List exported variables"),
+        ])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let mut env = ctx.env.lock().unwrap();
+            let unexport = args.get(1).map(|s| s.as_str()) == Some("-n");
+            let names = &args[if unexport { 2 } else { 1 }..];
+            if names.is_empty() || names == ["-p"] {
+                let mut exported: Vec<_> = env.exported_vars().collect();
+                exported.sort();
+                for (k, v) in exported {
+                    writeln!(io.stdout, "export {}={}", k, quote_word(v))?;
+                }
+                return Ok(0);
+            }
+            for arg in names {
+                let name = match arg.split_once('=') {
+                    Some((name, value)) => {
+                        env.set_var(name.to_string(), value.to_string());
+                        name
+                    }
+                    None => arg.as_str(),
+                };
+                if unexport { env.unexport(name) } else { env.export(name) }
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Unset;
+
+impl Builtin for Unset {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn usage(&self) -> &'static str {
+        "unset [-f] NAME..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Remove a variable, or a function with -f"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Words(&[("-f", "Remove functions instead of variables")])
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            if args.get(1).map(|s| s.as_str()) == Some("-f") {
+                let mut macros = ctx.macros.lock().unwrap();
+                for name in &args[2..] {
+                    macros.unset_function(name)?;
+                }
+                return Ok(0);
+            }
+            if let Some(arg) = args.get(1) {
+                ctx.env.lock().unwrap().remove_var(arg);
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Local;
+
+impl Builtin for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn usage(&self) -> &'static str {
+        "local NAME[=VALUE]..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Make variables local to the current function"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let mut env = ctx.env.lock().unwrap();
+            for arg in &args[1..] {
+                let (name, value) = match arg.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (arg.as_str(), None),
+                };
+                env.make_local(name)?;
+                if let Some(value) = value {
+                    env.set_var(name.to_string(), value.to_string());
+                }
+            }
+            Ok(0)
+        })
+    }
+}
+
+pub struct Return;
+
+impl Builtin for Return {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    fn usage(&self) -> &'static str {
+        "return [N]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Leave the current function or sourced file with status N"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            let env = ctx.env.lock().unwrap();
+            if env.call_depth() == 0 {
+                return Err(anyhow!("return: can only be used in a function or sourced file"));
+            }
+            let code = match args.get(1) {
+                Some(arg) => arg.parse().map_err(|_| anyhow!("return: {}: numeric argument required", arg))?,
+                None => env.last_status(),
+            };
+            Err(ReturnSignal(code).into())
+        })
+    }
+}
+
+pub struct Let;
+
+impl Builtin for Let {
+    fn name(&self) -> &'static str {
+        "let"
+    }
+
+    fn usage(&self) -> &'static str {
+        "let 'EXPR'..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Evaluate arithmetic; status is 1 when the result is 0"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], _io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            // Each argument is an expression; the status comes from the last one
+            if args.len() < 2 {
+                return Err(anyhow!("let: expression expected"));
+            }
+            let mut env = ctx.env.lock().unwrap();
+            let mut value = arith::Number::Int(0);
+            for expr in &args[1..] {
+                value = arith::evaluate(expr, &mut env).map_err(|e| anyhow!("let: {}", e))?;
+            }
+            Ok(if value.is_true() { 0 } else { 1 })
+        })
+    }
+}
+
+pub struct Math;
+
+impl Builtin for Math {
+    fn name(&self) -> &'static str {
+        "math"
+    }
+
+    fn usage(&self) -> &'static str {
+        "math 'EXPR'"
+    }
+
+    fn help(&self) -> &'static str {
+        "Evaluate arithmetic and print the result"
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Nothing
+    }
+
+    fn run<'a>(&'a self, args: &'a [String], io: &'a mut BuiltinIo, ctx: Context<'a>) -> BuiltinFuture<'a> {
+        Box::pin(async move {
+            // math 2 + 3: the words make one expression, and the result is printed
+            let expr = args[1..].join(" ");
+            let value = arith::evaluate(&expr, &mut ctx.env.lock().unwrap()).map_err(|e| anyhow!("math: {}", e))?;
+            writeln!(io.stdout, "{}", value)?;
+            Ok(if value.is_true() { 0 } else { 1 })
+        })
+    }
+}
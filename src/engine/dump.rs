@@ -0,0 +1,259 @@
+use crate::engine::builtins::BuiltinRegistry;
+use crate::engine::env::EnvManager;
+use crate::engine::executor::modern_tool;
+use crate::engine::expand::{expand_word_to_string, expand_words};
+use crate::engine::jobs::JobManager;
+use crate::engine::macros::MacroManager;
+use crate::engine::parser::{parse_input, tokenize, Command, CommandList, Compound, ParamExpansion, ParamOp, Word, WordPart};
+use anyhow::Result;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// For `chev parse` and `--dump-ast`: shows what each stage makes of `input` without running it.
+/// That's macro expansion, tokens, the AST as a tree and as JSON, then each simple
+/// command's argv and what it would run. Parse errors are returned as-is.
+pub async fn dump(input: &str, jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<String> {
+    let mut out = String::new();
+    let expanded = macros.lock().unwrap().expand_macro(input);
+    let source = expanded.as_deref().unwrap_or(input);
+    if let Some(expanded) = &expanded {
+        writeln!(out, "macro: {}", expanded)?;
+    }
+
+    writeln!(out, "tokens: {:?}", tokenize(source))?;
+    let list = parse_input(source)?;
+    writeln!(out, "ast:")?;
+    write_list(&mut out, &list, 1)?;
+    writeln!(out, "json:\n{}", serde_json::to_string_pretty(&list)?)?;
+
+    writeln!(out, "commands:")?;
+    let mut commands = Vec::new();
+    collect_commands(&list, &mut commands);
+    let search_path = env_mutex.lock().unwrap().get_var("PATH").cloned();
+    for command in commands {
+        writeln!(out, "  {}", command)?;
+        // Against the shell's current variables: nothing runs, so earlier assignments don't apply
+        let mut argv = Vec::new();
+        for assignment in &command.assignments {
+            let value = expand_word_to_string(&inert(&assignment.value), jobs, env_mutex, macros, builtins).await?;
+            argv.push(format!("{}={}", assignment.name, value));
+        }
+        let args = expand_words(&command.args.iter().map(inert).collect::<Vec<_>>(), jobs, env_mutex, macros, builtins).await?;
+        argv.extend(args.iter().cloned());
+        writeln!(out, "    argv: {:?}", argv)?;
+
+        let Some(name) = args.first() else { continue };
+        let runs = if macros.lock().unwrap().function(name).is_some() {
+            "function".to_string()
+        } else if matches!(name.as_str(), "source" | ".") || builtins.get(name).is_some() {
+            "builtin".to_string()
+        } else {
+            let rest: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+            let mapped = modern_tool(name, &rest);
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let find = |program: &str| which::which_in(program, search_path.as_deref(), &cwd).ok();
+            match (find(mapped), mapped != name) {
+                (Some(path), true) => format!("{} (mapped from {})", path.display(), name),
+                (Some(path), false) => path.display().to_string(),
+                // Like `resolve_command`, fall back to the original when the replacement is missing
+                (None, true) => match find(name) {
+                    Some(path) => format!("{} ({} is not installed)", path.display(), mapped),
+                    None => format!("not found ({} is not installed either)", mapped),
+                },
+                (None, false) => "not found".to_string(),
+            }
+        };
+        writeln!(out, "    runs: {}", runs)?;
+    }
+    Ok(out)
+}
+
+fn write_list(out: &mut String, list: &CommandList, depth: usize) -> std::fmt::Result {
+    let indent = "  ".repeat(depth);
+    match list {
+        CommandList::Pipeline(pipeline) => {
+            writeln!(out, "{}Pipeline{}", indent, if pipeline.background { " &" } else { "" })?;
+            for command in &pipeline.commands {
+                write_command(out, command, depth + 1)?;
+            }
+        }
+        CommandList::And(left, right) | CommandList::Or(left, right) => {
+            writeln!(out, "{}{}", indent, if matches!(list, CommandList::And(..)) { "And" } else { "Or" })?;
+            write_list(out, left, depth + 1)?;
+            write_list(out, right, depth + 1)?;
+        }
+        CommandList::Sequence(items) => {
+            writeln!(out, "{}Sequence", indent)?;
+            for item in items {
+                write_list(out, item, depth + 1)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_command(out: &mut String, command: &Command, depth: usize) -> std::fmt::Result {
+    let indent = "  ".repeat(depth);
+    match command.compound.as_deref() {
+        Some(compound) => write_compound(out, compound, depth)?,
+        None => {
+            writeln!(out, "{}Command", indent)?;
+            for assignment in &command.assignments {
+                writeln!(out, "{}  assign {}", indent, assignment)?;
+            }
+            for arg in &command.args {
+                writeln!(out, "{}  word {}", indent, arg)?;
+            }
+        }
+    }
+    for redirection in &command.redirections {
+        writeln!(out, "{}  redirect {}", indent, redirection)?;
+    }
+    Ok(())
+}
+
+fn write_compound(out: &mut String, compound: &Compound, depth: usize) -> std::fmt::Result {
+    let indent = "  ".repeat(depth);
+    let section = |out: &mut String, label: &str, list: &CommandList| {
+        writeln!(out, "{}  {}", indent, label)?;
+        write_list(out, list, depth + 2)
+    };
+    match compound {
+        Compound::If { branches, else_branch } => {
+            writeln!(out, "{}If", indent)?;
+            for (i, (condition, body)) in branches.iter().enumerate() {
+                section(out, if i == 0 { "if" } else { "elif" }, condition)?;
+                section(out, "then", body)?;
+            }
+            if let Some(body) = else_branch {
+                section(out, "else", body)?;
+            }
+        }
+        Compound::While { condition, body, until } => {
+            writeln!(out, "{}{}", indent, if *until { "Until" } else { "While" })?;
+            section(out, "condition", condition)?;
+            section(out, "do", body)?;
+        }
+        Compound::For { var, words, body } => {
+            let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+            writeln!(out, "{}For {} in {}", indent, var, words.join(" "))?;
+            section(out, "do", body)?;
+        }
+        Compound::Case { word, arms } => {
+            writeln!(out, "{}Case {}", indent, word)?;
+            for arm in arms {
+                let patterns: Vec<String> = arm.patterns.iter().map(|p| p.to_string()).collect();
+                section(out, &format!("{})", patterns.join("|")), &arm.body)?;
+            }
+        }
+        Compound::Function { name, body } => {
+            writeln!(out, "{}Function {}", indent, name)?;
+            write_list(out, body, depth + 1)?;
+        }
+        Compound::Subshell(list) => {
+            writeln!(out, "{}Subshell", indent)?;
+            write_list(out, list, depth + 1)?;
+        }
+        Compound::Group(list) => {
+            writeln!(out, "{}Group", indent)?;
+            write_list(out, list, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Every simple command in the list, including those inside compound commands.
+fn collect_commands<'a>(list: &'a CommandList, commands: &mut Vec<&'a Command>) {
+    match list {
+        CommandList::Pipeline(pipeline) => {
+            for command in &pipeline.commands {
+                match command.compound.as_deref() {
+                    None => commands.push(command),
+                    Some(Compound::If { branches, else_branch }) => {
+                        for (condition, body) in branches {
+                            collect_commands(condition, commands);
+                            collect_commands(body, commands);
+                        }
+                        if let Some(body) = else_branch {
+                            collect_commands(body, commands);
+                        }
+                    }
+                    Some(Compound::While { condition, body, .. }) => {
+                        collect_commands(condition, commands);
+                        collect_commands(body, commands);
+                    }
+                    Some(Compound::For { body, .. } | Compound::Function { body, .. }) => collect_commands(body, commands),
+                    Some(Compound::Case { arms, .. }) => {
+                        for arm in arms {
+                            collect_commands(&arm.body, commands);
+                        }
+                    }
+                    Some(Compound::Subshell(list) | Compound::Group(list)) => collect_commands(list, commands),
+                }
+            }
+        }
+        CommandList::And(left, right) | CommandList::Or(left, right) => {
+            collect_commands(left, commands);
+            collect_commands(right, commands);
+        }
+        CommandList::Sequence(items) => {
+            for item in items {
+                collect_commands(item, commands);
+            }
+        }
+    }
+}
+
+/// A copy of the word with command and process substitutions left as their source text,
+/// so expanding it can't run anything.
+fn inert(word: &Word) -> Word {
+    let parts = word.parts.iter().map(|part| match part {
+        WordPart::CommandSubst(_) | WordPart::ProcessSubst { .. } => WordPart::SingleQuoted(part.to_string()),
+        WordPart::DoubleQuoted(inner) => WordPart::DoubleQuoted(inert(&Word { parts: inner.clone() }).parts),
+        WordPart::Arithmetic(expr) => WordPart::Arithmetic(inert(expr)),
+        WordPart::Param(param) => WordPart::Param(ParamExpansion {
+            name: param.name.clone(),
+            op: match &param.op {
+                ParamOp::Default(w) => ParamOp::Default(inert(w)),
+                ParamOp::Assign(w) => ParamOp::Assign(inert(w)),
+                ParamOp::RemoveSuffix { pattern, longest } => ParamOp::RemoveSuffix { pattern: inert(pattern), longest: *longest },
+                ParamOp::RemovePrefix { pattern, longest } => ParamOp::RemovePrefix { pattern: inert(pattern), longest: *longest },
+                op => op.clone(),
+            },
+        }),
+        part => part.clone(),
+    });
+    Word { parts: parts.collect() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::test_support::Shell;
+
+    #[tokio::test]
+    async fn test_dump() {
+        let shell = Shell::new();
+        shell.run("chev_dump_fn() { :; }; CHEV_DUMP_VAR='a b'").await.unwrap();
+
+        let out = dump("chev_dump_fn \"$CHEV_DUMP_VAR\" $((2 * 3)) $(date) > out; export X", &shell.jobs, &shell.env, &shell.macros, &shell.builtins).await.unwrap();
+        let (before_json, json) = out.split_once("json:\n").unwrap();
+        let (json, commands) = json.split_once("commands:\n").unwrap();
+        assert_eq!(
+            before_json,
+            "tokens: [\"chev_dump_fn\", \"\\\"$CHEV_DUMP_VAR\\\"\", \"$((2 * 3))\", \"$(date)\", \">\", \"out\", \";\", \"export\", \"X\"]\n\
+             ast:\n  Sequence\n    Pipeline\n      Command\n        word chev_dump_fn\n        word \"${CHEV_DUMP_VAR}\"\n        \
+             word $((2 * 3))\n        word $(date)\n        redirect > out\n    Pipeline\n      Command\n        word export\n        word X\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(json["Sequence"][1]["Pipeline"]["commands"][0]["args"][0]["parts"][0]["Literal"], "export");
+        // Substitutions are shown, not run
+        assert_eq!(
+            commands,
+            "  chev_dump_fn \"${CHEV_DUMP_VAR}\" $((2 * 3)) $(date) > out\n    argv: [\"chev_dump_fn\", \"a b\", \"6\", \"$(date)\"]\n    runs: function\n\
+             \x20 export X\n    argv: [\"export\", \"X\"]\n    runs: builtin\n"
+        );
+
+        assert!(dump("echo a | | b", &shell.jobs, &shell.env, &shell.macros, &shell.builtins).await.is_err());
+    }
+}
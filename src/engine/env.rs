@@ -1,36 +1,151 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::env;
 use anyhow::{Result, anyhow};
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Notify;
 
+/// Options toggled with `set -o NAME` / `set +o NAME`.
+pub const SHELL_OPTIONS: &[&str] = &[
+    // Fail on globs that match nothing (zsh) instead of passing them through (bash)
+    "nomatch",
+    // A pipeline fails if any stage fails, not just the last
+    "pipefail",
+    // Stop at the first failing command (`set -e`)
+    "errexit",
+    // Print each command before running it (`set -x`)
+    "xtrace",
+];
+
+/// State saved by a function call and restored when it returns.
+#[derive(Serialize, Deserialize)]
+struct Frame {
+    positional: Vec<String>,
+    // Variables made `local`, with the value each had before the call (None if unset)
+    // and whether it was exported
+    saved: HashMap<String, (Option<String>, bool)>,
+}
+
+/// Variables replaced for a single command (`FOO=bar func`): name, old value, whether it was exported.
+pub type SavedVars = Vec<(String, Option<String>, bool)>;
+
+/// Serializes to what a subshell carries over: signal handling is per process and stays behind.
+#[derive(Serialize, Deserialize)]
 pub struct EnvManager {
-    // Persistent environment variables (inherited + exported)
+    // Shell variables, inherited ones included
     vars: HashMap<String, String>,
+    // Names of the variables passed on to child processes
+    exported: HashSet<String>,
     // Directory stack for pushd/popd
     dir_stack: Vec<PathBuf>,
+    // Enabled shell options
+    options: HashSet<String>,
+    // Status of the last pipeline ($?) and of each of its stages ($PIPESTATUS)
+    last_status: i32,
+    pipestatus: Vec<i32>,
+    // Positional parameters ($1..$N) and the calls that shadowed the outer ones
+    positional: Vec<String>,
+    frames: Vec<Frame>,
+    // $0: the script being run, or the shell itself
+    arg0: String,
+    // Nesting of if/while conditions and `&&`/`||` left sides, where `set -e` doesn't apply
+    conditions: usize,
+    // Commands set with `trap`, by signal number with 0 for EXIT
+    traps: BTreeMap<i32, String>,
+    // Signals the shell catches rather than dying from, and those caught but not yet handled
+    #[serde(skip)]
+    caught: HashSet<i32>,
+    #[serde(skip)]
+    pending: Vec<i32>,
+    // Raised as each caught signal is queued, for `wait`
+    #[serde(skip)]
+    signalled: Arc<Notify>,
+    // Signals ignored with `trap ''`, and how each was handled before
+    #[serde(skip)]
+    ignored: HashMap<i32, SigAction>,
+    // Reading commands from a terminal rather than running a script
+    #[serde(skip)]
+    interactive: bool,
 }
 
 impl EnvManager {
     pub fn new() -> Self {
         let vars: HashMap<String, String> = env::vars().collect();
+        // Whatever we inherited gets passed on
+        let exported = vars.keys().cloned().collect();
         Self {
             vars,
+            exported,
             dir_stack: Vec::new(),
+            options: HashSet::new(),
+            last_status: 0,
+            pipestatus: vec![0],
+            positional: Vec::new(),
+            frames: Vec::new(),
+            arg0: "chev".to_string(),
+            conditions: 0,
+            traps: BTreeMap::new(),
+            caught: HashSet::new(),
+            pending: Vec::new(),
+            signalled: Arc::new(Notify::new()),
+            ignored: HashMap::new(),
+            interactive: false,
         }
     }
 
+    /// Sets a shell variable. It only reaches child processes if it's exported.
     pub fn set_var(&mut self, key: String, value: String) {
-        unsafe {
-            env::set_var(&key, &value);
-        }
         self.vars.insert(key, value);
     }
 
     pub fn remove_var(&mut self, key: &str) {
-        unsafe {
-            env::remove_var(key);
-        }
         self.vars.remove(key);
+        self.exported.remove(key);
+    }
+
+    pub fn export(&mut self, key: &str) {
+        self.exported.insert(key.to_string());
+    }
+
+    /// `export -n`: keeps the variable but stops passing it to children.
+    pub fn unexport(&mut self, key: &str) {
+        self.exported.remove(key);
+    }
+
+    pub fn is_exported(&self, key: &str) -> bool {
+        self.exported.contains(key)
+    }
+
+    /// The environment a child process starts with.
+    pub fn exported_vars(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.vars.iter().filter(|(k, _)| self.exported.contains(*k))
+    }
+
+    /// Sets and exports `assignments` until `restore_vars` is given the result,
+    /// for `FOO=bar` in front of a function or other shell code.
+    pub fn set_temporary(&mut self, assignments: Vec<(String, String)>) -> SavedVars {
+        let mut saved = Vec::new();
+        for (name, value) in assignments {
+            saved.push((name.clone(), self.vars.get(&name).cloned(), self.is_exported(&name)));
+            self.set_var(name.clone(), value);
+            self.export(&name);
+        }
+        saved
+    }
+
+    pub fn restore_vars(&mut self, saved: SavedVars) {
+        // In reverse, so `A=1 A=2 f` ends up with the value from before both
+        for (name, value, exported) in saved.into_iter().rev() {
+            match value {
+                Some(value) => self.set_var(name.clone(), value),
+                None => self.remove_var(&name),
+            }
+            if !exported {
+                self.unexport(&name);
+            }
+        }
     }
 
     pub fn get_var(&self, key: &str) -> Option<&String> {
@@ -41,6 +156,177 @@ impl EnvManager {
         &self.vars
     }
 
+    pub fn set_option(&mut self, name: &str, enabled: bool) -> Result<()> {
+        if !SHELL_OPTIONS.contains(&name) {
+            return Err(anyhow!("set: unknown option: {}", name));
+        }
+        if enabled {
+            self.options.insert(name.to_string());
+        } else {
+            self.options.remove(name);
+        }
+        Ok(())
+    }
+
+    pub fn option(&self, name: &str) -> bool {
+        self.options.contains(name)
+    }
+
+    pub fn set_status(&mut self, status: i32, pipestatus: Vec<i32>) {
+        self.last_status = status;
+        self.pipestatus = pipestatus;
+    }
+
+    pub fn last_status(&self) -> i32 {
+        self.last_status
+    }
+
+    pub fn pipestatus(&self) -> &[i32] {
+        &self.pipestatus
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn set_positional(&mut self, args: Vec<String>) {
+        self.positional = args;
+    }
+
+    pub fn arg0(&self) -> &str {
+        &self.arg0
+    }
+
+    pub fn set_arg0(&mut self, name: String) {
+        self.arg0 = name;
+    }
+
+    pub fn enter_condition(&mut self) {
+        self.conditions += 1;
+    }
+
+    pub fn leave_condition(&mut self) {
+        self.conditions = self.conditions.saturating_sub(1);
+    }
+
+    /// Whether a failing command should stop the script right now.
+    pub fn errexit_applies(&self) -> bool {
+        self.option("errexit") && self.conditions == 0
+    }
+
+    /// Sets the command run on `signal` (0 for EXIT), or with `None` goes back to the default.
+    /// An empty command means the signal is ignored.
+    pub fn set_trap(&mut self, signal: i32, command: Option<String>) {
+        match command {
+            Some(command) => self.traps.insert(signal, command),
+            None => self.traps.remove(&signal),
+        };
+    }
+
+    pub fn trap(&self, signal: i32) -> Option<&str> {
+        self.traps.get(&signal).map(String::as_str)
+    }
+
+    pub fn traps(&self) -> impl Iterator<Item = (i32, &String)> {
+        self.traps.iter().map(|(&signal, command)| (signal, command))
+    }
+
+    /// Notes that the shell now catches `signal`. False if it already did.
+    pub fn catch(&mut self, signal: i32) -> bool {
+        self.caught.insert(signal)
+    }
+
+    pub fn is_caught(&self, signal: i32) -> bool {
+        self.caught.contains(&signal)
+    }
+
+    /// Notes that `signal` is now ignored, keeping the first `previous` handler to go back to.
+    pub fn ignore_signal(&mut self, signal: i32, previous: SigAction) {
+        self.ignored.entry(signal).or_insert(previous);
+    }
+
+    /// Forgets that `signal` was ignored, returning the handler it had before.
+    pub fn stop_ignoring(&mut self, signal: i32) -> Option<SigAction> {
+        self.ignored.remove(&signal)
+    }
+
+    /// A caught signal, to be handled by `run_traps` between commands.
+    pub fn queue_signal(&mut self, signal: i32) {
+        self.pending.push(signal);
+        self.signalled.notify_waiters();
+    }
+
+    /// Notified each time a caught signal is queued.
+    pub fn signalled(&self) -> Arc<Notify> {
+        Arc::clone(&self.signalled)
+    }
+
+    /// The first signal waiting to be handled, if any.
+    pub fn pending_signal(&self) -> Option<i32> {
+        self.pending.first().copied()
+    }
+
+    pub fn take_signals(&mut self) -> Vec<i32> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Entering a subshell: traps other than ignored signals don't carry over, and the
+    /// subshell catches nothing until told to. Returns the signals to give back their defaults.
+    pub fn reset_traps(&mut self) -> Vec<i32> {
+        self.traps.retain(|&signal, command| signal != 0 && command.is_empty());
+        self.pending.clear();
+        // Those left are the ignored signals. The parent's handlers don't run here,
+        // so undoing a `trap ''` means the default
+        self.ignored = self.traps.keys()
+            .map(|&signal| (signal, SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty())))
+            .collect();
+        let caught = std::mem::take(&mut self.caught);
+        caught.into_iter().filter(|signal| !self.traps.contains_key(signal)).collect()
+    }
+
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Enters a function call: `args` become `$1..$N` until `pop_frame`.
+    pub fn push_frame(&mut self, args: Vec<String>) {
+        let positional = std::mem::replace(&mut self.positional, args);
+        self.frames.push(Frame { positional, saved: HashMap::new() });
+    }
+
+    /// Leaves a function call, restoring the caller's parameters and anything made `local`.
+    pub fn pop_frame(&mut self) {
+        let Some(frame) = self.frames.pop() else { return };
+        self.positional = frame.positional;
+        for (name, (value, exported)) in frame.saved {
+            match value {
+                Some(value) => self.set_var(name.clone(), value),
+                None => self.remove_var(&name),
+            }
+            // `local X; export X` only exports it for the call
+            if !exported {
+                self.unexport(&name);
+            }
+        }
+    }
+
+    /// Number of function calls currently running.
+    pub fn call_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// `local name`: the variable gets its old value back when the current function returns.
+    pub fn make_local(&mut self, name: &str) -> Result<()> {
+        let current = (self.vars.get(name).cloned(), self.is_exported(name));
+        let frame = self.frames.last_mut().ok_or_else(|| anyhow!("local: can only be used in a function"))?;
+        frame.saved.entry(name.to_string()).or_insert(current);
+        Ok(())
+    }
+
     // Directory Stack Logic
     pub fn pushd(&mut self, path: PathBuf) -> Result<()> {
         let current = env::current_dir()?;
@@ -71,7 +357,7 @@ impl EnvManager {
 
     // Modern feature: Smart Path addition
     pub fn add_to_path(&mut self, new_path: &str, at_front: bool) {
-        let current_path = env::var("PATH").unwrap_or_default();
+        let current_path = self.vars.get("PATH").cloned().unwrap_or_default();
         let mut paths: Vec<PathBuf> = env::split_paths(&current_path).collect();
 
         // Deduplicate
@@ -87,6 +373,7 @@ impl EnvManager {
         if let Ok(joined) = env::join_paths(paths) {
             let joined_str = joined.to_string_lossy().to_string();
             self.set_var("PATH".to_string(), joined_str);
+            self.export("PATH");
         }
     }
 
@@ -114,11 +401,109 @@ mod tests {
         let mut manager = EnvManager::new();
         manager.set_var("CHEV_TEST".to_string(), "rocks".to_string());
         assert_eq!(manager.get_var("CHEV_TEST"), Some(&"rocks".to_string()));
-        assert_eq!(env::var("CHEV_TEST").unwrap(), "rocks");
+        manager.export("CHEV_TEST");
+        assert!(manager.exported_vars().any(|(k, v)| k == "CHEV_TEST" && v == "rocks"));
 
         manager.remove_var("CHEV_TEST");
         assert_eq!(manager.get_var("CHEV_TEST"), None);
-        assert!(env::var("CHEV_TEST").is_err());
+        assert!(!manager.is_exported("CHEV_TEST"));
+        assert!(manager.exported_vars().all(|(k, _)| k != "CHEV_TEST"));
+    }
+
+    #[test]
+    fn test_set_var_stays_in_the_shell() {
+        let mut manager = EnvManager::new();
+        manager.set_var("CHEV_UNEXPORTED".to_string(), "1".to_string());
+        assert!(env::var("CHEV_UNEXPORTED").is_err());
+        assert!(!manager.is_exported("CHEV_UNEXPORTED"));
+        assert!(manager.exported_vars().all(|(k, _)| k != "CHEV_UNEXPORTED"));
+    }
+
+    #[test]
+    fn test_export_reaches_children() {
+        let mut manager = EnvManager::new();
+        manager.set_var("CHEV_SHARED".to_string(), "shared".to_string());
+        manager.set_var("CHEV_PRIVATE".to_string(), "private".to_string());
+        manager.export("CHEV_SHARED");
+        // Children get exactly what the executor gives them
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg("echo \"$CHEV_SHARED:$CHEV_PRIVATE\"")
+            .env_clear()
+            .envs(manager.exported_vars())
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "shared:\n");
+        // Exporting doesn't leak into the shell's own process environment either
+        assert!(env::var("CHEV_SHARED").is_err());
+    }
+
+    #[test]
+    fn test_exported_vars() {
+        let mut manager = EnvManager::new();
+        let exported = |m: &EnvManager, key: &str| m.exported_vars().any(|(k, _)| k == key);
+        assert!(exported(&manager, "PATH"));
+
+        manager.set_var("CHEV_LOCAL".to_string(), "1".to_string());
+        assert!(!exported(&manager, "CHEV_LOCAL"));
+        manager.export("CHEV_LOCAL");
+        assert!(exported(&manager, "CHEV_LOCAL"));
+        manager.unexport("CHEV_LOCAL");
+        assert!(!exported(&manager, "CHEV_LOCAL"));
+        assert_eq!(manager.get_var("CHEV_LOCAL").map(String::as_str), Some("1"));
+
+        let saved = manager.set_temporary(vec![("CHEV_LOCAL".to_string(), "2".to_string()), ("CHEV_TEMP".to_string(), "x".to_string())]);
+        assert!(exported(&manager, "CHEV_LOCAL") && exported(&manager, "CHEV_TEMP"));
+        manager.restore_vars(saved);
+        assert_eq!(manager.get_var("CHEV_LOCAL").map(String::as_str), Some("1"));
+        assert!(!manager.is_exported("CHEV_LOCAL"));
+        assert_eq!(manager.get_var("CHEV_TEMP"), None);
+    }
+
+    #[test]
+    fn test_function_frames() {
+        let mut manager = EnvManager::new();
+        manager.set_var("CHEV_FRAME_TEST".to_string(), "outer".to_string());
+        assert!(manager.make_local("CHEV_FRAME_TEST").is_err());
+
+        manager.push_frame(vec!["a".to_string(), "b".to_string()]);
+        manager.make_local("CHEV_FRAME_TEST").unwrap();
+        manager.make_local("CHEV_FRAME_NEW").unwrap();
+        manager.set_var("CHEV_FRAME_TEST".to_string(), "inner".to_string());
+        manager.set_var("CHEV_FRAME_NEW".to_string(), "x".to_string());
+        assert_eq!(manager.positional(), &["a", "b"]);
+        assert_eq!(manager.call_depth(), 1);
+
+        manager.pop_frame();
+        assert_eq!(manager.get_var("CHEV_FRAME_TEST"), Some(&"outer".to_string()));
+        assert_eq!(manager.get_var("CHEV_FRAME_NEW"), None);
+        assert!(manager.positional().is_empty());
+
+        // A local that's exported inside the call goes back to being unexported
+        manager.push_frame(Vec::new());
+        manager.make_local("CHEV_FRAME_TEST").unwrap();
+        manager.export("CHEV_FRAME_TEST");
+        manager.pop_frame();
+        assert!(!manager.is_exported("CHEV_FRAME_TEST"));
+        assert_eq!(manager.get_var("CHEV_FRAME_TEST"), Some(&"outer".to_string()));
+        manager.remove_var("CHEV_FRAME_TEST");
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut manager = EnvManager::new();
+        manager.set_trap(0, Some("echo bye".to_string()));
+        manager.set_trap(2, Some("echo int".to_string()));
+        manager.set_trap(15, Some(String::new()));
+        assert!(manager.catch(2) && manager.catch(15) && !manager.catch(2));
+        manager.queue_signal(2);
+        assert_eq!(manager.pending_signal(), Some(2));
+
+        // A subshell keeps only the ignored signal, and gives INT back its default
+        assert_eq!(manager.reset_traps(), vec![2]);
+        assert_eq!(manager.traps().collect::<Vec<_>>(), vec![(15, &String::new())]);
+        assert_eq!(manager.pending_signal(), None);
+        assert!(!manager.is_caught(15));
     }
 
     #[test]
@@ -140,12 +525,14 @@ mod tests {
         let mut manager = EnvManager::new();
         let test_path = "/tmp/chev-test-bin";
         
+        // What children are given, not just what the shell holds
+        let path = |manager: &EnvManager| manager.exported_vars().find(|(k, _)| *k == "PATH").map(|(_, v)| v.clone()).unwrap();
         manager.add_to_path(test_path, true);
-        let current_path = env::var("PATH").unwrap();
+        let current_path = path(&manager);
         assert!(current_path.starts_with(test_path));
         
         manager.add_to_path("/usr/local/bin", false);
-        let updated_path = env::var("PATH").unwrap();
+        let updated_path = path(&manager);
         assert!(updated_path.contains("/usr/local/bin"));
     }
 }
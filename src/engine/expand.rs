@@ -0,0 +1,481 @@
+use crate::engine::arith;
+use crate::engine::builtins::BuiltinRegistry;
+use crate::engine::env::EnvManager;
+use crate::engine::executor::{capture_output, process_substitution};
+use crate::engine::glob;
+use crate::engine::jobs::JobManager;
+use crate::engine::macros::MacroManager;
+use crate::engine::parser::{ParamExpansion, ParamOp, Word, WordPart};
+use anyhow::{Result, anyhow};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_IFS: &str = " \t\n";
+
+/// One field before globbing: the plain text, and the same text as a glob
+/// pattern with anything that was quoted escaped.
+#[derive(Default)]
+struct Field {
+    text: String,
+    pattern: String,
+}
+
+/// Accumulates expanded text into fields, splitting unquoted expansion results on IFS.
+struct FieldBuilder {
+    fields: Vec<Field>,
+    current: Field,
+    // A quoted empty string ("") still produces a field
+    has_current: bool,
+}
+
+impl FieldBuilder {
+    fn new() -> Self {
+        Self { fields: Vec::new(), current: Field::default(), has_current: false }
+    }
+
+    fn push_quoted(&mut self, text: &str) {
+        self.current.text.push_str(text);
+        self.current.pattern.push_str(&glob::escape(text));
+        self.has_current = true;
+    }
+
+    fn push_unquoted(&mut self, text: &str) {
+        self.current.text.push_str(text);
+        self.current.pattern.push_str(text);
+        self.has_current = true;
+    }
+
+    fn push_split(&mut self, text: &str, ifs: &str) {
+        for c in text.chars() {
+            if ifs.contains(c) {
+                self.finish_field();
+            } else {
+                self.push_unquoted(c.encode_utf8(&mut [0; 4]));
+            }
+        }
+    }
+
+    fn finish_field(&mut self) {
+        if self.has_current {
+            self.fields.push(std::mem::take(&mut self.current));
+            self.has_current = false;
+        }
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        self.finish_field();
+        self.fields
+    }
+}
+
+/// Expands a word into zero or more arguments: braces, tilde, parameters and
+/// command substitutions, field splitting, then filename globbing.
+pub async fn expand_word(word: &Word, jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<Vec<String>> {
+    let (ifs, nomatch) = {
+        let env = env_mutex.lock().unwrap();
+        (env.get_var("IFS").cloned().unwrap_or_else(|| DEFAULT_IFS.to_string()), env.option("nomatch"))
+    };
+    let mut args = Vec::new();
+
+    for mut word in brace_expand(word) {
+        expand_tilde(&mut word, &env_mutex.lock().unwrap());
+
+        let mut builder = FieldBuilder::new();
+        for part in &word.parts {
+            match part {
+                WordPart::Literal(s) => builder.push_unquoted(s),
+                WordPart::SingleQuoted(s) => builder.push_quoted(s),
+                WordPart::Escaped(c) => builder.push_quoted(c.encode_utf8(&mut [0; 4])),
+                WordPart::DoubleQuoted(inner) if inner.iter().any(is_all_args) => {
+                    // "$@" gives each positional parameter its own field
+                    for part in inner {
+                        if is_all_args(part) {
+                            let args = env_mutex.lock().unwrap().positional().to_vec();
+                            for (i, arg) in args.iter().enumerate() {
+                                if i > 0 {
+                                    builder.finish_field();
+                                }
+                                builder.push_quoted(arg);
+                            }
+                        } else {
+                            let text = expand_parts_to_string(std::slice::from_ref(part), jobs, env_mutex, macros, builtins).await?;
+                            builder.push_quoted(&text);
+                        }
+                    }
+                }
+                WordPart::DoubleQuoted(inner) => {
+                    let text = expand_parts_to_string(inner, jobs, env_mutex, macros, builtins).await?;
+                    builder.push_quoted(&text);
+                }
+                WordPart::Param(param) => {
+                    let value = expand_param(param, jobs, env_mutex, macros, builtins).await?;
+                    builder.push_split(&value, &ifs);
+                }
+                WordPart::CommandSubst(list) => {
+                    let output = capture_output(list, jobs, env_mutex, macros, builtins).await?;
+                    builder.push_split(&output, &ifs);
+                }
+                WordPart::Arithmetic(expr) => {
+                    let value = expand_arithmetic(expr, jobs, env_mutex, macros, builtins).await?;
+                    builder.push_split(&value, &ifs);
+                }
+                WordPart::ProcessSubst { list, output } => {
+                    builder.push_quoted(&process_substitution(list, *output, jobs, env_mutex, macros)?);
+                }
+            }
+        }
+
+        for field in builder.finish() {
+            if !glob::has_magic(&field.pattern) {
+                args.push(field.text);
+                continue;
+            }
+            let matches = glob::expand(&field.pattern)?;
+            if !matches.is_empty() {
+                args.extend(matches);
+            } else if nomatch {
+                return Err(anyhow!("no matches found: {}", field.text));
+            } else {
+                args.push(field.text);
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+#[derive(Clone, Copy)]
+enum BraceToken<'a> {
+    Char(char),
+    Part(&'a WordPart),
+}
+
+/// Expands `{a,b}` alternatives in the unquoted text of a word, e.g. `src/{main,lib}.rs`.
+fn brace_expand(word: &Word) -> Vec<Word> {
+    if !word.parts.iter().any(|p| matches!(p, WordPart::Literal(s) if s.contains('{'))) {
+        return vec![word.clone()];
+    }
+
+    let tokens: Vec<BraceToken> = word.parts.iter()
+        .flat_map(|part| match part {
+            WordPart::Literal(s) => s.chars().map(BraceToken::Char).collect(),
+            other => vec![BraceToken::Part(other)],
+        })
+        .collect();
+
+    expand_braces(tokens).into_iter()
+        .map(|tokens| {
+            let mut parts = Vec::new();
+            let mut literal = String::new();
+            for token in tokens {
+                match token {
+                    BraceToken::Char(c) => literal.push(c),
+                    BraceToken::Part(part) => {
+                        if !literal.is_empty() {
+                            parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+                        }
+                        parts.push(part.clone());
+                    }
+                }
+            }
+            if !literal.is_empty() {
+                parts.push(WordPart::Literal(literal));
+            }
+            Word { parts }
+        })
+        .collect()
+}
+
+fn expand_braces(tokens: Vec<BraceToken>) -> Vec<Vec<BraceToken>> {
+    for open in 0..tokens.len() {
+        if !matches!(tokens[open], BraceToken::Char('{')) {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut bounds = vec![open];
+        let mut close = None;
+        for (i, token) in tokens.iter().enumerate().skip(open) {
+            match token {
+                BraceToken::Char('{') => depth += 1,
+                BraceToken::Char('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(i);
+                        break;
+                    }
+                }
+                BraceToken::Char(',') if depth == 1 => bounds.push(i),
+                _ => {}
+            }
+        }
+
+        // `{}` and `{x}` are left alone, as in `find -exec {} \;`
+        let Some(close) = close else { continue };
+        if bounds.len() == 1 {
+            continue;
+        }
+        bounds.push(close);
+
+        let mut expanded = Vec::new();
+        for alt in bounds.windows(2) {
+            let mut next = tokens[..open].to_vec();
+            next.extend_from_slice(&tokens[alt[0] + 1..alt[1]]);
+            next.extend_from_slice(&tokens[close + 1..]);
+            expanded.extend(expand_braces(next));
+        }
+        return expanded;
+    }
+    vec![tokens]
+}
+
+/// Replaces a leading `~` or `~user` with the home directory.
+fn expand_tilde(word: &mut Word, env: &EnvManager) {
+    let Some(WordPart::Literal(literal)) = word.parts.first() else { return };
+    let Some(rest) = literal.strip_prefix('~') else { return };
+    let (user, tail) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        // `~$USER` and `~"name"` are not tilde prefixes
+        None if word.parts.len() == 1 => (rest, ""),
+        None => return,
+    };
+
+    let home = if user.is_empty() {
+        env.get_var("HOME").cloned().or_else(|| dirs::home_dir().map(|p| p.to_string_lossy().into_owned()))
+    } else {
+        user_home(user)
+    };
+    let Some(home) = home else { return };
+
+    let mut replacement = vec![WordPart::SingleQuoted(home)];
+    if !tail.is_empty() {
+        replacement.push(WordPart::Literal(tail.to_string()));
+    }
+    word.parts.splice(0..1, replacement);
+}
+
+fn user_home(user: &str) -> Option<String> {
+    let name = std::ffi::CString::new(user).ok()?;
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    let rc = unsafe { libc::getpwnam_r(name.as_ptr(), &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    let dir = unsafe { std::ffi::CStr::from_ptr(pwd.pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+pub async fn expand_words(words: &[Word], jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for word in words {
+        args.extend(expand_word(word, jobs, env_mutex, macros, builtins).await?);
+    }
+    Ok(args)
+}
+
+/// Expands a word for use as a pattern, escaping the parts that were quoted.
+pub async fn expand_pattern(word: &Word, jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<String> {
+    let mut pattern = String::new();
+    for part in &word.parts {
+        let text = Box::pin(expand_parts_to_string(std::slice::from_ref(part), jobs, env_mutex, macros, builtins)).await?;
+        match part {
+            WordPart::Literal(_) | WordPart::Param(_) | WordPart::CommandSubst(_) | WordPart::Arithmetic(_) | WordPart::ProcessSubst { .. } => pattern.push_str(&text),
+            _ => pattern.push_str(&glob::escape(&text)),
+        }
+    }
+    Ok(pattern)
+}
+
+/// Expands a word to exactly one string, without field splitting.
+/// Used for redirection targets and parameter operands.
+pub async fn expand_word_to_string(word: &Word, jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<String> {
+    expand_parts_to_string(&word.parts, jobs, env_mutex, macros, builtins).await
+}
+
+async fn expand_parts_to_string(parts: &[WordPart], jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<String> {
+    let mut out = String::new();
+    for part in parts {
+        match part {
+            WordPart::Literal(s) | WordPart::SingleQuoted(s) => out.push_str(s),
+            WordPart::Escaped(c) => out.push(*c),
+            WordPart::DoubleQuoted(inner) => {
+                out.push_str(&Box::pin(expand_parts_to_string(inner, jobs, env_mutex, macros, builtins)).await?);
+            }
+            WordPart::Param(param) => out.push_str(&Box::pin(expand_param(param, jobs, env_mutex, macros, builtins)).await?),
+            WordPart::CommandSubst(list) => out.push_str(&capture_output(list, jobs, env_mutex, macros, builtins).await?),
+            WordPart::Arithmetic(expr) => out.push_str(&expand_arithmetic(expr, jobs, env_mutex, macros, builtins).await?),
+            WordPart::ProcessSubst { list, output } => out.push_str(&process_substitution(list, *output, jobs, env_mutex, macros)?),
+        }
+    }
+    Ok(out)
+}
+
+/// `$(( expr ))`: expands what's inside, then evaluates it.
+async fn expand_arithmetic(expr: &Word, jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<String> {
+    let text = Box::pin(expand_parts_to_string(&expr.parts, jobs, env_mutex, macros, builtins)).await?;
+    let value = arith::evaluate(&text, &mut env_mutex.lock().unwrap()).map_err(|e| anyhow!("$(({})): {}", text.trim(), e))?;
+    Ok(value.to_string())
+}
+
+fn is_all_args(part: &WordPart) -> bool {
+    matches!(part, WordPart::Param(ParamExpansion { name, op: ParamOp::Plain }) if name == "@")
+}
+
+fn lookup_param(name: &str, env: &EnvManager) -> Option<String> {
+    match name {
+        "$" => Some(std::process::id().to_string()),
+        "?" => Some(env.last_status().to_string()),
+        "#" => Some(env.positional().len().to_string()),
+        "@" | "*" => Some(env.positional().join(" ")),
+        "0" => Some(env.arg0().to_string()),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            name.parse::<usize>().ok().and_then(|n| env.positional().get(n.checked_sub(1)?).cloned())
+        }
+        "PIPESTATUS" => Some(env.pipestatus().iter().map(|s| s.to_string()).collect::<Vec<_>>().join(" ")),
+        _ => env.get_var(name).cloned(),
+    }
+}
+
+async fn expand_param(param: &ParamExpansion, jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<String> {
+    let value = lookup_param(&param.name, &env_mutex.lock().unwrap());
+
+    let expanded = match &param.op {
+        ParamOp::Plain => value.unwrap_or_default(),
+        ParamOp::Length => value.map(|v| v.chars().count()).unwrap_or(0).to_string(),
+        ParamOp::Default(word) => match value {
+            Some(v) if !v.is_empty() => v,
+            _ => expand_word_to_string(word, jobs, env_mutex, macros, builtins).await?,
+        },
+        ParamOp::Assign(word) => match value {
+            Some(v) if !v.is_empty() => v,
+            _ => {
+                let v = expand_word_to_string(word, jobs, env_mutex, macros, builtins).await?;
+                env_mutex.lock().unwrap().set_var(param.name.clone(), v.clone());
+                v
+            }
+        },
+        ParamOp::RemoveSuffix { pattern, longest } => {
+            let value = value.unwrap_or_default();
+            let pattern = expand_pattern(pattern, jobs, env_mutex, macros, builtins).await?;
+            remove_suffix(&value, &pattern, *longest).to_string()
+        }
+        ParamOp::RemovePrefix { pattern, longest } => {
+            let value = value.unwrap_or_default();
+            let pattern = expand_pattern(pattern, jobs, env_mutex, macros, builtins).await?;
+            remove_prefix(&value, &pattern, *longest).to_string()
+        }
+    };
+    Ok(expanded)
+}
+
+fn char_boundaries(s: &str) -> Vec<usize> {
+    s.char_indices().map(|(i, _)| i).chain(std::iter::once(s.len())).collect()
+}
+
+fn remove_suffix<'a>(value: &'a str, pattern: &str, longest: bool) -> &'a str {
+    let mut starts = char_boundaries(value);
+    // Shortest suffix starts as late as possible
+    if !longest {
+        starts.reverse();
+    }
+    for start in starts {
+        if glob::matches(pattern, &value[start..]) {
+            return &value[..start];
+        }
+    }
+    value
+}
+
+fn remove_prefix<'a>(value: &'a str, pattern: &str, longest: bool) -> &'a str {
+    let mut ends = char_boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    for end in ends {
+        if glob::matches(pattern, &value[..end]) {
+            return &value[end..];
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::parser::parse_pipeline;
+    use crate::engine::test_support::Shell;
+
+    #[tokio::test]
+    async fn test_expand_simple_vars() {
+        let shell = Shell::new();
+        shell.set("CHEV_EXP_NAME", "chev");
+        assert_eq!(shell.expand("echo $CHEV_EXP_NAME ${CHEV_EXP_NAME}!").await, vec!["echo", "chev", "chev!"]);
+        assert_eq!(shell.expand("echo $CHEV_EXP_MISSING").await, vec!["echo"]);
+    }
+
+    #[tokio::test]
+    async fn test_expand_respects_quotes() {
+        let shell = Shell::new();
+        shell.set("CHEV_EXP_WORDS", "a b  c");
+        assert_eq!(shell.expand("echo $CHEV_EXP_WORDS").await, vec!["echo", "a", "b", "c"]);
+        assert_eq!(shell.expand("echo \"$CHEV_EXP_WORDS\"").await, vec!["echo", "a b  c"]);
+        assert_eq!(shell.expand("echo '$CHEV_EXP_WORDS'").await, vec!["echo", "$CHEV_EXP_WORDS"]);
+        assert_eq!(shell.expand("echo \"\"").await, vec!["echo", ""]);
+    }
+
+    #[tokio::test]
+    async fn test_expand_param_operators() {
+        let shell = Shell::new();
+        shell.set("CHEV_EXP_FILE", "src/engine/parser.rs");
+        assert_eq!(shell.expand("echo ${CHEV_EXP_UNSET:-fallback}").await, vec!["echo", "fallback"]);
+        assert_eq!(shell.expand("echo ${#CHEV_EXP_FILE}").await, vec!["echo", "20"]);
+        assert_eq!(shell.expand("echo ${CHEV_EXP_FILE%.rs}").await, vec!["echo", "src/engine/parser"]);
+        assert_eq!(shell.expand("echo ${CHEV_EXP_FILE#*/}").await, vec!["echo", "engine/parser.rs"]);
+        assert_eq!(shell.expand("echo ${CHEV_EXP_FILE##*/}").await, vec!["echo", "parser.rs"]);
+        assert_eq!(shell.expand("echo ${CHEV_EXP_FILE%%/*}").await, vec!["echo", "src"]);
+
+        assert_eq!(shell.expand("echo ${CHEV_EXP_ASSIGN:=set}").await, vec!["echo", "set"]);
+        assert_eq!(shell.env.lock().unwrap().get_var("CHEV_EXP_ASSIGN"), Some(&"set".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_expand_positional() {
+        let shell = Shell::new();
+        let args = "abcdefghijkl".chars().map(String::from).collect();
+        shell.env.lock().unwrap().set_positional(args);
+        assert_eq!(shell.expand("echo ${10} $10 ${12} ${13:-none} ${00}").await, vec!["echo", "j", "a0", "l", "none"]);
+    }
+
+    #[tokio::test]
+    async fn test_expand_command_substitution() {
+        let shell = Shell::new();
+        assert_eq!(shell.expand("echo $(printf 'a b\\n\\n')").await, vec!["echo", "a", "b"]);
+        assert_eq!(shell.expand("echo \"$(printf 'a b\\n')\"").await, vec!["echo", "a b"]);
+        assert_eq!(shell.expand("echo x`echo y`z").await, vec!["echo", "xyz"]);
+        assert_eq!(shell.expand("echo \"$(echo \"$(echo nested)\")\"").await, vec!["echo", "nested"]);
+    }
+
+    #[tokio::test]
+    async fn test_expand_braces_tilde_and_globs() {
+        let shell = Shell::new();
+        shell.set("HOME", "/home/chev");
+        assert_eq!(shell.expand("echo src/{main,lib}.rs a{1,{2,3}}").await, vec!["echo", "src/main.rs", "src/lib.rs", "a1", "a2", "a3"]);
+        assert_eq!(shell.expand("echo {} {x} '{a,b}'").await, vec!["echo", "{}", "{x}", "{a,b}"]);
+        assert_eq!(shell.expand("echo ~ ~/bin ~chev_no_such_user a~").await, vec!["echo", "/home/chev", "/home/chev/bin", "~chev_no_such_user", "a~"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        for f in ["a.rs", "b.rs", "c.txt"] {
+            std::fs::write(dir.path().join(f), "").unwrap();
+        }
+        shell.set("CHEV_EXP_DIR", &dir.path().display().to_string());
+        let base = dir.path().display().to_string();
+        assert_eq!(shell.expand("echo $CHEV_EXP_DIR/*.rs").await, vec!["echo".to_string(), format!("{}/a.rs", base), format!("{}/b.rs", base)]);
+        assert_eq!(shell.expand("echo \"$CHEV_EXP_DIR/*.rs\"").await, vec!["echo".to_string(), format!("{}/*.rs", base)]);
+        assert_eq!(shell.expand("echo $CHEV_EXP_DIR/*.go").await, vec!["echo".to_string(), format!("{}/*.go", base)]);
+
+        shell.env.lock().unwrap().set_option("nomatch", true).unwrap();
+        let (_, pipeline) = parse_pipeline("echo $CHEV_EXP_DIR/*.go").unwrap();
+        assert!(expand_words(&pipeline.commands[0].args, &shell.jobs, &shell.env, &shell.macros, &shell.builtins).await.is_err());
+    }
+}
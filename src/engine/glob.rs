@@ -0,0 +1,287 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Patterns use `\` to mark characters that came from quoted text, so `"*".rs` only matches `*.rs`.
+const META: &[char] = &['*', '?', '[', '\\'];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+/// Escapes glob metacharacters so `text` only matches itself.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if META.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Strips the escapes added by `escape`, giving back the literal text.
+pub fn unescape(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// True if the pattern contains an unescaped `*`, `?` or a complete `[...]` class.
+pub fn has_magic(pattern: &str) -> bool {
+    compile(pattern).iter().any(|t| !matches!(t, Token::Char(_)))
+}
+
+fn compile(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(Token::Char(chars[i + 1]));
+                i += 2;
+                continue;
+            }
+            '*' => {
+                // Runs of stars behave like one
+                if tokens.last() != Some(&Token::Star) {
+                    tokens.push(Token::Star);
+                }
+            }
+            '?' => tokens.push(Token::Any),
+            '[' => match compile_class(&chars[i + 1..]) {
+                Some((token, used)) => {
+                    tokens.push(token);
+                    i += used + 1;
+                    continue;
+                }
+                // An unterminated bracket is just a character, e.g. the `[` command
+                None => tokens.push(Token::Char('[')),
+            },
+            c => tokens.push(Token::Char(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Parses the body of a `[...]` class, returning the token and the number of chars used including `]`.
+fn compile_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < chars.len() {
+        let mut c = chars[i];
+        // A `]` right after the opening bracket is a member, not the end
+        if c == ']' && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        if c == '\\' && i + 1 < chars.len() {
+            i += 1;
+            c = chars[i];
+        }
+        if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+fn token_matches(token: &Token, c: char) -> bool {
+    match token {
+        Token::Char(expected) => *expected == c,
+        Token::Any => true,
+        Token::Star => unreachable!(),
+        Token::Class { negated, ranges } => ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated,
+    }
+}
+
+fn match_tokens(tokens: &[Token], text: &str) -> bool {
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < tokens.len() && tokens[pi] == Token::Star {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if pi < tokens.len() && token_matches(&tokens[pi], t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    tokens[pi..].iter().all(|t| *t == Token::Star)
+}
+
+/// Matches `text` against a shell pattern. Unlike filename expansion, `*` also matches `/`.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    match_tokens(&compile(pattern), text)
+}
+
+/// Expands a pattern against the filesystem, returning sorted paths.
+/// A `**` segment matches any number of directories.
+pub fn expand(pattern: &str) -> Result<Vec<String>> {
+    let dirs_only = pattern.len() > 1 && pattern.ends_with('/');
+    let mut segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+
+    // (path on disk, path as the user will see it)
+    let mut candidates = if pattern.starts_with('/') {
+        vec![(PathBuf::from("/"), "/".to_string())]
+    } else {
+        vec![(PathBuf::from("."), String::new())]
+    };
+
+    // `**` at the end matches every file below, not just directories
+    let trailing_globstar = segments.last() == Some(&"**");
+    if trailing_globstar {
+        segments.pop();
+    }
+
+    for (i, segment) in segments.iter().enumerate() {
+        let is_last = i == segments.len() - 1 && !trailing_globstar;
+        let mut next = Vec::new();
+
+        for (path, display) in &candidates {
+            if *segment == "**" {
+                next.push((path.clone(), display.clone()));
+                walk(path, display, &mut |p, d, is_dir| {
+                    if is_dir {
+                        next.push((p, d));
+                    }
+                });
+            } else if !has_magic(segment) {
+                let name = unescape(segment);
+                let p = path.join(&name);
+                if fs::symlink_metadata(&p).is_ok() {
+                    next.push((p, join_display(display, &name)));
+                }
+            } else {
+                let tokens = compile(segment);
+                let show_hidden = segment.starts_with('.');
+                let Ok(entries) = fs::read_dir(path) else { continue };
+                for entry in entries.flatten() {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if name.starts_with('.') && !show_hidden {
+                        continue;
+                    }
+                    if !match_tokens(&tokens, &name) {
+                        continue;
+                    }
+                    let p = entry.path();
+                    if !is_last && !p.is_dir() {
+                        continue;
+                    }
+                    next.push((p, join_display(display, &name)));
+                }
+            }
+        }
+        candidates = next;
+    }
+
+    if trailing_globstar {
+        let mut all = Vec::new();
+        for (path, display) in &candidates {
+            walk(path, display, &mut |p, d, _| all.push((p, d)));
+        }
+        candidates = all;
+    }
+
+    let mut results: Vec<String> = candidates
+        .into_iter()
+        .filter(|(p, d)| !d.is_empty() && (!dirs_only || p.is_dir()))
+        .map(|(_, d)| if dirs_only { format!("{}/", d) } else { d })
+        .collect();
+    results.sort();
+    results.dedup();
+    Ok(results)
+}
+
+fn join_display(display: &str, name: &str) -> String {
+    if display.is_empty() {
+        name.to_string()
+    } else if display.ends_with('/') {
+        format!("{}{}", display, name)
+    } else {
+        format!("{}/{}", display, name)
+    }
+}
+
+/// Visits every non-hidden entry below `path` without following directory symlinks.
+fn walk(path: &Path, display: &str, visit: &mut dyn FnMut(PathBuf, String, bool)) {
+    let Ok(entries) = fs::read_dir(path) else { return };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        let d = join_display(display, &name);
+        visit(entry.path(), d.clone(), is_dir);
+        if is_dir {
+            walk(&entry.path(), &d, visit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("ma?n*", "main.rs"));
+        assert!(!matches("*.rs", "main.go"));
+        assert!(matches("*", ""));
+        assert!(matches("[a-c]at", "bat"));
+        assert!(!matches("[!a-c]at", "bat"));
+        assert!(matches("\\*.rs", "*.rs"));
+        assert!(!matches("\\*.rs", "main.rs"));
+        assert!(!has_magic("["));
+    }
+
+    #[test]
+    fn test_expand_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/engine")).unwrap();
+        for f in ["src/main.rs", "src/engine/glob.rs", "src/lib.txt", "src/.hidden.rs"] {
+            fs::write(root.join(f), "").unwrap();
+        }
+        let base = root.display().to_string();
+        let strip = |v: Vec<String>| v.into_iter().map(|p| p[base.len() + 1..].to_string()).collect::<Vec<_>>();
+
+        assert_eq!(strip(expand(&format!("{}/src/*.rs", base)).unwrap()), vec!["src/main.rs"]);
+        assert_eq!(strip(expand(&format!("{}/src/**/*.rs", base)).unwrap()), vec!["src/engine/glob.rs", "src/main.rs"]);
+        assert_eq!(strip(expand(&format!("{}/src/*/", base)).unwrap()), vec!["src/engine/"]);
+        assert!(expand(&format!("{}/src/*.go", base)).unwrap().is_empty());
+    }
+}
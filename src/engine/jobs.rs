@@ -1,35 +1,270 @@
-use nix::unistd::Pid;
+use anyhow::{anyhow, Result};
+use nix::sys::signal::Signal;
+use nix::sys::termios::{tcgetattr, tcsetattr, SetArg, Termios};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{getpgrp, tcsetpgrp, Pid};
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::os::fd::{BorrowedFd, OwnedFd};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum JobStatus {
     Running,
-    Suspended,
+    /// Stopped by this signal, e.g. SIGTSTP from Ctrl+Z or SIGTTIN from reading the terminal
+    Stopped(#[serde(with = "signal_number")] Signal),
+    /// Every member has exited; the code is the last member's
+    Done(i32),
+    /// The last member was killed by this signal
+    Killed(#[serde(with = "signal_number")] Signal),
+    /// Gone without its status ever reaching us, e.g. reaped while SIGCHLD was ignored
+    Lost,
+}
+
+impl JobStatus {
+    /// Done or killed, so there's nothing left to wait for.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Done(_) | JobStatus::Killed(_) | JobStatus::Lost)
+    }
+
+    /// What `$?` is after waiting on a job in this state.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            JobStatus::Running => 0,
+            JobStatus::Done(code) => *code,
+            JobStatus::Stopped(signal) | JobStatus::Killed(signal) => 128 + *signal as i32,
+            // What bash's `wait` gives for a pid it has no status for
+            JobStatus::Lost => 127,
+        }
+    }
 }
 
 impl fmt::Display for JobStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             JobStatus::Running => write!(f, "Running"),
-            JobStatus::Suspended => write!(f, "Stopped"),
+            JobStatus::Stopped(Signal::SIGTTIN) => write!(f, "Stopped (tty input)"),
+            JobStatus::Stopped(Signal::SIGTTOU) => write!(f, "Stopped (tty output)"),
+            JobStatus::Stopped(Signal::SIGSTOP) => write!(f, "Stopped (signal)"),
+            JobStatus::Stopped(_) => write!(f, "Stopped"),
+            JobStatus::Done(0) => write!(f, "Done"),
+            JobStatus::Done(code) => write!(f, "Exit {}", code),
+            JobStatus::Killed(signal) => write!(f, "{}", signal_description(*signal)),
+            JobStatus::Lost => write!(f, "Done (status unknown)"),
         }
     }
 }
 
+/// How a job killed by `signal` is described, the way bash words it.
+fn signal_description(signal: Signal) -> &'static str {
+    match signal {
+        Signal::SIGHUP => "Hangup",
+        Signal::SIGINT => "Interrupt",
+        Signal::SIGQUIT => "Quit",
+        Signal::SIGABRT => "Aborted",
+        Signal::SIGKILL => "Killed",
+        Signal::SIGSEGV => "Segmentation fault",
+        Signal::SIGPIPE => "Broken pipe",
+        Signal::SIGTERM => "Terminated",
+        other => other.as_str(),
+    }
+}
+
 use std::time::Instant;
 
-#[derive(Debug, Clone)]
+/// Signals and pids are handed to subshells as plain numbers.
+mod signal_number {
+    use nix::sys::signal::Signal;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(signal: &Signal, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(*signal as i32)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Signal, D::Error> {
+        Signal::try_from(i32::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+mod pid_number {
+    use nix::unistd::Pid;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(pid: &Pid, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(pid.as_raw())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pid, D::Error> {
+        i32::deserialize(deserializer).map(Pid::from_raw)
+    }
+}
+
+/// One process of a job's pipeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Member {
+    #[serde(with = "pid_number")]
+    pub pid: Pid,
+    /// Its stage of the pipeline, e.g. `grep foo` in `cat log | grep foo`
+    pub cmd: String,
+    pub status: JobStatus,
+}
+
+impl Member {
+    pub fn new(pid: Pid, cmd: String, status: JobStatus) -> Self {
+        Self { pid, cmd, status }
+    }
+
+    /// Records what `waitpid` said about it.
+    pub fn update(&mut self, wait_status: WaitStatus) {
+        self.status = match wait_status {
+            WaitStatus::Exited(_, code) => JobStatus::Done(code),
+            WaitStatus::Signaled(_, signal, _) => JobStatus::Killed(signal),
+            WaitStatus::Stopped(_, signal) => JobStatus::Stopped(signal),
+            WaitStatus::Continued(_) => JobStatus::Running,
+            _ => return,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Job {
     pub id: usize,
+    #[serde(with = "pid_number")]
     pub pgid: Pid,
+    /// Every process in the pipeline, in order
+    pub members: Vec<Member>,
     pub cmd: String,
     pub status: JobStatus,
+    #[serde(skip, default = "Instant::now")]
     pub start_time: Instant,
+    /// The terminal's modes when it stopped, which it gets back when resumed with `fg`
+    #[serde(skip)]
+    pub tmodes: Option<Termios>,
+    // Changed since the user last saw it
+    changed: bool,
+    /// Started by the shell that started this subshell, so it isn't ours to wait on
+    #[serde(skip)]
+    pub inherited: bool,
 }
 
+impl Job {
+    /// Members that haven't exited yet.
+    pub fn live_members(&self) -> impl Iterator<Item = &Member> {
+        self.members.iter().filter(|member| !member.status.is_finished())
+    }
+
+    /// Marks stopped members as running again, once they've been sent SIGCONT.
+    pub fn resume(&mut self) {
+        for member in &mut self.members {
+            if matches!(member.status, JobStatus::Stopped(_)) {
+                member.status = JobStatus::Running;
+            }
+        }
+        self.update();
+    }
+
+    /// Checks each member without blocking, updating the job's status from whatever changed.
+    fn poll(&mut self) {
+        // Only its parent can wait for it; here it stays as it was when the subshell started
+        if self.inherited {
+            return;
+        }
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+        for member in self.members.iter_mut().filter(|member| !member.status.is_finished()) {
+            match waitpid(member.pid, Some(flags)) {
+                Ok(wait_status) => member.update(wait_status),
+                // Already reaped without us, so whatever it exited with is gone
+                Err(nix::errno::Errno::ECHILD) => member.status = JobStatus::Lost,
+                Err(_) => {}
+            }
+        }
+        self.update();
+    }
+
+    /// Works out the job's status from its members': finished once they all are, with the
+    /// last one's status, like `$?` for a pipeline, and stopped if any of them is.
+    pub fn update(&mut self) {
+        let status = if self.live_members().next().is_none() {
+            self.members.last().map_or(JobStatus::Done(0), |member| member.status)
+        } else {
+            self.live_members()
+                .find_map(|member| match member.status {
+                    JobStatus::Stopped(signal) => Some(JobStatus::Stopped(signal)),
+                    _ => None,
+                })
+                .unwrap_or(JobStatus::Running)
+        };
+        if status != self.status {
+            // Being resumed isn't worth announcing
+            self.changed |= status != JobStatus::Running;
+            self.status = status;
+        }
+    }
+}
+
+/// The terminal on stdin, while a foreground job has it.
+pub struct Foreground {
+    shell_pgid: Pid,
+    shell_tmodes: Option<Termios>,
+}
+
+impl Foreground {
+    /// Hands the terminal to `pgid`, with the modes it stopped with if it's being resumed.
+    pub fn give(pgid: Pid, tmodes: Option<&Termios>) -> Self {
+        let stdin = Self::stdin();
+        let shell_tmodes = tcgetattr(stdin).ok();
+        if let Some(tmodes) = tmodes {
+            let _ = tcsetattr(stdin, SetArg::TCSADRAIN, tmodes);
+        }
+        let _ = tcsetpgrp(stdin, pgid);
+        Self { shell_pgid: getpgrp(), shell_tmodes }
+    }
+
+    /// Takes the terminal back and puts the shell's modes back, returning the job's,
+    /// which are worth keeping if it stopped rather than exited.
+    pub fn take_back(self) -> Option<Termios> {
+        let stdin = Self::stdin();
+        let _ = tcsetpgrp(stdin, self.shell_pgid);
+        let job_tmodes = tcgetattr(stdin).ok();
+        if let Some(tmodes) = &self.shell_tmodes {
+            let _ = tcsetattr(stdin, SetArg::TCSADRAIN, tmodes);
+        }
+        job_tmodes
+    }
+
+    fn stdin() -> BorrowedFd<'static> {
+        unsafe { BorrowedFd::borrow_raw(libc::STDIN_FILENO) }
+    }
+}
+
+/// The helper process behind a `<(...)` or `>(...)` word.
+#[derive(Debug)]
+pub struct ProcessSubst {
+    pub pid: Pid,
+    /// The shell's end of the helper's pipe, which the word's `/dev/fd/N` path names
+    pub fd: Arc<OwnedFd>,
+}
+
+/// Serializes to the job table a subshell starts with.
+#[derive(Serialize, Deserialize)]
 pub struct JobManager {
     jobs: Vec<Job>,
     next_id: usize,
+    // Job ids from least to most recently started, stopped or resumed; `%+` is the last
+    recent: Vec<usize>,
+    // Members of disowned jobs, reaped quietly
+    #[serde(skip)]
+    disowned: Vec<Pid>,
+    /// Off in subshells, whose pipelines stay in the subshell's process group
+    #[serde(skip)]
+    pub job_control: bool,
+    /// Helpers started while expanding a command, until its pipeline claims them
+    #[serde(skip)]
+    pub substitutions: Vec<ProcessSubst>,
+    // Raised after every `reap`, for `wait`
+    #[serde(skip)]
+    reaped: Arc<Notify>,
 }
 
 impl JobManager {
@@ -37,20 +272,118 @@ impl JobManager {
         Self {
             jobs: Vec::new(),
             next_id: 1,
+            recent: Vec::new(),
+            disowned: Vec::new(),
+            job_control: true,
+            substitutions: Vec::new(),
+            reaped: Arc::new(Notify::new()),
         }
     }
 
-    pub fn add_job(&mut self, pgid: Pid, cmd: String, status: JobStatus) -> usize {
+    /// Adds a job made up of `members`, in pipeline order, taking its status from theirs.
+    pub fn add_job(&mut self, pgid: Pid, members: Vec<Member>, cmd: String) -> usize {
         let id = self.next_id;
-        self.jobs.push(Job { id, pgid, cmd, status, start_time: Instant::now() });
+        let mut job = Job {
+            id,
+            pgid,
+            members,
+            cmd,
+            status: JobStatus::Running,
+            start_time: Instant::now(),
+            tmodes: None,
+            changed: false,
+            inherited: false,
+        };
+        job.update();
+        // The shell says itself when a job it adds has stopped or finished
+        job.changed = false;
+        self.jobs.push(job);
         self.next_id += 1;
+        self.recent.push(id);
         id
     }
 
     pub fn remove_job(&mut self, pgid: Pid) {
         self.jobs.retain(|j| j.pgid != pgid);
-        if self.jobs.is_empty() {
-            self.next_id = 1;
+        self.forget_removed();
+    }
+
+    /// Takes a job out of the table while the shell waits on it in the foreground,
+    /// so the reaper leaves its members alone.
+    pub fn take_job(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        let job = self.jobs.remove(index);
+        self.recent.retain(|&r| r != id);
+        Some(job)
+    }
+
+    /// Puts back a job from `take_job` under its old id, as the current job.
+    /// Whatever happened to it while it was out has already been reported.
+    pub fn put_back(&mut self, mut job: Job) {
+        job.changed = false;
+        let id = job.id;
+        let index = self.jobs.partition_point(|other| other.id < id);
+        self.jobs.insert(index, job);
+        self.next_id = self.next_id.max(id + 1);
+        self.recent.push(id);
+    }
+
+    /// Stops tracking a job; its members are still reaped, but never reported.
+    pub fn disown(&mut self, id: usize) {
+        if let Some(index) = self.jobs.iter().position(|job| job.id == id) {
+            let job = self.jobs.remove(index);
+            self.disowned.extend(job.live_members().map(|member| member.pid));
+            self.forget_removed();
+        }
+    }
+
+    /// Makes `id` the current job, `%+`, with the old current one becoming `%-`.
+    pub fn touch(&mut self, id: usize) {
+        self.recent.retain(|&r| r != id);
+        self.recent.push(id);
+    }
+
+    pub fn current(&self) -> Option<&Job> {
+        self.recent.last().and_then(|&id| self.find_job_by_id(id))
+    }
+
+    pub fn previous(&self) -> Option<&Job> {
+        self.recent.iter().rev().nth(1).and_then(|&id| self.find_job_by_id(id))
+    }
+
+    /// `+` for the current job, `-` for the previous one, otherwise a space.
+    pub fn marker(&self, id: usize) -> char {
+        match self.recent.iter().rev().position(|&r| r == id) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    /// The job a spec names: `%N` or a bare `N` by id, `%%`, `%+` or `%` for the current job,
+    /// `%-` for the previous one, `%name` by what its command starts with and `%?text` by
+    /// what it contains.
+    pub fn find_job(&self, spec: &str) -> Result<&Job> {
+        let not_found = || anyhow!("{}: no such job", spec);
+        let spec_body = spec.strip_prefix('%').unwrap_or(spec);
+        if let Ok(id) = spec_body.parse::<usize>() {
+            return self.find_job_by_id(id).ok_or_else(not_found);
+        }
+        if !spec.starts_with('%') {
+            return Err(not_found());
+        }
+        let matches: Vec<&Job> = match spec_body {
+            "" | "%" | "+" => return self.current().ok_or_else(not_found),
+            "-" => return self.previous().ok_or_else(not_found),
+            _ => match spec_body.strip_prefix('?') {
+                Some(text) => self.jobs.iter().filter(|job| job.cmd.contains(text)).collect(),
+                None => self.jobs.iter().filter(|job| job.cmd.starts_with(spec_body)).collect(),
+            },
+        };
+        match matches[..] {
+            [job] => Ok(job),
+            [] => Err(not_found()),
+            _ => Err(anyhow!("{}: ambiguous job spec", spec)),
         }
     }
 
@@ -61,19 +394,99 @@ impl JobManager {
     pub fn find_job_by_id(&self, id: usize) -> Option<&Job> {
         self.jobs.iter().find(|j| j.id == id)
     }
+
+    pub fn find_job_by_id_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|j| j.id == id)
+    }
+
+    /// Reaps whatever job members have exited, stopped or continued, without blocking.
+    /// Only job members are waited on, so foreground pipelines keep their own statuses.
+    pub fn reap(&mut self) {
+        let mut stopped = Vec::new();
+        for job in &mut self.jobs {
+            let was_stopped = matches!(job.status, JobStatus::Stopped(_));
+            job.poll();
+            if !was_stopped && matches!(job.status, JobStatus::Stopped(_)) {
+                stopped.push(job.id);
+            }
+        }
+        // A job that stops becomes the current one, as if it had just been suspended
+        for id in stopped {
+            self.touch(id);
+        }
+        self.disowned.retain(|&pid| matches!(waitpid(pid, Some(WaitPidFlag::WNOHANG)), Ok(WaitStatus::StillAlive)));
+        self.reaped.notify_waiters();
+    }
+
+    /// Notified each time jobs are reaped, so waiting for one doesn't mean polling.
+    pub fn reaped(&self) -> Arc<Notify> {
+        Arc::clone(&self.reaped)
+    }
+
+    /// Takes the table a subshell was handed. Pipelines stay in the subshell's process group
+    /// and leave the terminal alone, and its jobs still show up, but only the shell that
+    /// started them can wait for them.
+    pub fn inherit(&mut self) {
+        self.job_control = false;
+        for job in &mut self.jobs {
+            job.inherited = true;
+        }
+    }
+
+    /// Lines like `[1]  Done    sleep 10` for jobs that finished or stopped since the last call.
+    /// Finished jobs are forgotten once reported.
+    pub fn take_notices(&mut self) -> Vec<String> {
+        let notices = self.jobs.iter()
+            .filter(|job| job.changed)
+            .map(|job| format!("[{}]{}  {:<24}{}", job.id, self.marker(job.id), job.status.to_string(), job.cmd))
+            .collect();
+        for job in &mut self.jobs {
+            job.changed = false;
+        }
+        self.jobs.retain(|job| !job.status.is_finished());
+        self.forget_removed();
+        notices
+    }
+
+    fn forget_removed(&mut self) {
+        let jobs = &self.jobs;
+        self.recent.retain(|&id| jobs.iter().any(|job| job.id == id));
+        if self.jobs.is_empty() {
+            self.next_id = 1;
+        }
+    }
+}
+
+/// Reaps background jobs whenever SIGCHLD arrives; they're announced before the next prompt.
+pub fn reap_on_sigchld(jobs: &Arc<Mutex<JobManager>>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigchld = signal(SignalKind::child())?;
+    let jobs = Arc::clone(jobs);
+    tokio::spawn(async move {
+        while sigchld.recv().await.is_some() {
+            jobs.lock().unwrap().reap();
+        }
+    });
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use nix::unistd::Pid;
+    use std::process::Stdio;
+    use std::time::Duration;
+
+    fn add(manager: &mut JobManager, pid: Pid, cmd: &str, status: JobStatus) -> usize {
+        manager.add_job(pid, vec![Member::new(pid, cmd.to_string(), status)], cmd.to_string())
+    }
 
     #[test]
     fn test_job_management() {
         let mut manager = JobManager::new();
         let pid = Pid::from_raw(1234);
-        let id = manager.add_job(pid, "sleep 100".to_string(), JobStatus::Running);
-        
+        let id = add(&mut manager, pid, "sleep 100", JobStatus::Running);
+
         assert_eq!(id, 1);
         assert_eq!(manager.get_jobs().len(), 1);
         assert_eq!(manager.find_job_by_id(1).unwrap().pgid, pid);
@@ -81,4 +494,110 @@ mod tests {
         manager.remove_job(pid);
         assert_eq!(manager.get_jobs().len(), 0);
     }
+
+    #[test]
+    fn test_foreground_keeps_id() {
+        let mut manager = JobManager::new();
+        let (one, two) = (Pid::from_raw(1001), Pid::from_raw(1002));
+        add(&mut manager, one, "vim", JobStatus::Stopped(Signal::SIGTSTP));
+        add(&mut manager, two, "sleep 100", JobStatus::Running);
+
+        // `fg %1` takes it out while it runs, and it comes back under the same id when stopped again
+        let job = manager.take_job(1).unwrap();
+        assert_eq!(manager.get_jobs().len(), 1);
+        manager.put_back(job);
+        assert_eq!(manager.get_jobs().iter().map(|job| job.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(manager.current().unwrap().id, 1);
+
+        // Even when it was the only job, its id isn't handed out while it's away
+        manager.remove_job(two);
+        let job = manager.take_job(1).unwrap();
+        let three = Pid::from_raw(1003);
+        assert_eq!(add(&mut manager, three, "make", JobStatus::Running), 3);
+        manager.put_back(job);
+        assert_eq!(manager.get_jobs().iter().map(|job| job.id).collect::<Vec<_>>(), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_job_specs() {
+        let mut manager = JobManager::new();
+        let (one, two, three) = (Pid::from_raw(1001), Pid::from_raw(1002), Pid::from_raw(1003));
+        add(&mut manager, one, "sleep 100", JobStatus::Running);
+        add(&mut manager, two, "vim notes.txt", JobStatus::Running);
+        add(&mut manager, three, "sleep 200 | cat", JobStatus::Running);
+
+        let id = |manager: &JobManager, spec: &str| manager.find_job(spec).map(|job| job.id).map_err(|e| e.to_string());
+        assert_eq!(id(&manager, "%2"), Ok(2));
+        assert_eq!(id(&manager, "2"), Ok(2));
+        assert_eq!(id(&manager, "%+"), Ok(3));
+        assert_eq!(id(&manager, "%%"), Ok(3));
+        assert_eq!(id(&manager, "%"), Ok(3));
+        assert_eq!(id(&manager, "%-"), Ok(2));
+        assert_eq!(id(&manager, "%vim"), Ok(2));
+        assert_eq!(id(&manager, "%?notes"), Ok(2));
+        assert_eq!(id(&manager, "%sleep"), Err("%sleep: ambiguous job spec".to_string()));
+        assert_eq!(id(&manager, "%4"), Err("%4: no such job".to_string()));
+        assert_eq!(id(&manager, "%emacs"), Err("%emacs: no such job".to_string()));
+
+        manager.touch(1);
+        assert_eq!((manager.marker(1), manager.marker(3), manager.marker(2)), ('+', '-', ' '));
+        manager.remove_job(one);
+        assert_eq!(id(&manager, "%+"), Ok(3));
+        assert_eq!(id(&manager, "%-"), Ok(2));
+    }
+
+    #[test]
+    // The manager reaps the children, not their `Child` handles
+    #[allow(clippy::zombie_processes)]
+    fn test_reap_and_notices() {
+        let spawn = |script: &str, stdin: Stdio| {
+            std::process::Command::new("sh").arg("-c").arg(script).stdin(stdin).spawn().unwrap()
+        };
+        let pid = |child: &std::process::Child| Pid::from_raw(child.id() as i32);
+        // Reaps until `done` holds, rather than guessing how long the children take
+        let reap_until = |manager: &mut JobManager, done: &dyn Fn(&JobManager) -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            manager.reap();
+            while !done(manager) {
+                assert!(Instant::now() < deadline, "children didn't finish in time");
+                std::thread::sleep(Duration::from_millis(10));
+                manager.reap();
+            }
+        };
+        let mut manager = JobManager::new();
+        // The last member holds out until its stdin closes
+        let (first, mut last) = (spawn("exit 0", Stdio::null()), spawn("read line; exit 3", Stdio::piped()));
+        let members = vec![Member::new(pid(&first), "true".to_string(), JobStatus::Running), Member::new(pid(&last), "false".to_string(), JobStatus::Running)];
+        manager.add_job(pid(&first), members, "true | false".to_string());
+        let killed = pid(&spawn("kill -9 $$", Stdio::null()));
+        add(&mut manager, killed, "sh", JobStatus::Running);
+
+        reap_until(&mut manager, &|manager| manager.get_jobs().iter().all(|job| job.members[0].status.is_finished()));
+        // The pipeline isn't done until its last member is
+        let pipeline = manager.find_job_by_id(1).unwrap();
+        assert_eq!(pipeline.status, JobStatus::Running);
+        assert_eq!(pipeline.members[0].status, JobStatus::Done(0));
+        assert_eq!(pipeline.live_members().map(|member| member.pid).collect::<Vec<_>>(), vec![pid(&last)]);
+        assert_eq!(manager.take_notices(), vec![format!("[2]+  {:<24}sh", "Killed")]);
+
+        drop(last.stdin.take());
+        reap_until(&mut manager, &|manager| manager.find_job_by_id(1).unwrap().status.is_finished());
+        assert_eq!(manager.find_job_by_id(1).unwrap().status, JobStatus::Done(3));
+        assert_eq!(manager.take_notices(), vec![format!("[1]+  {:<24}true | false", "Exit 3")]);
+        assert!(manager.get_jobs().is_empty());
+        assert!(manager.take_notices().is_empty());
+    }
+
+    #[test]
+    fn test_lost_status() {
+        // Reaped behind the manager's back, so ECHILD is all waitpid can say about it
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        let pid = Pid::from_raw(child.id() as i32);
+        let mut manager = JobManager::new();
+        add(&mut manager, pid, "true", JobStatus::Running);
+        manager.reap();
+        assert_eq!(manager.take_notices(), vec![format!("[1]+  {:<24}true", "Done (status unknown)")]);
+        assert_eq!(JobStatus::Lost.exit_code(), 127);
+    }
 }
@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Serialize, Deserialize};
 use std::fs;
 use anyhow::{Result, Context};
+use crate::engine::parser::{parse_input, CommandList};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Macro {
@@ -12,7 +13,13 @@ pub struct Macro {
 pub struct MacroManager {
     macros: HashMap<String, Macro>,
     abbreviations: HashMap<String, String>,
+    // Shell functions, saved as source text and parsed again on load
+    functions: HashMap<String, CommandList>,
     config_path: std::path::PathBuf,
+    functions_path: std::path::PathBuf,
+    // Only functions defined at the prompt are saved, not those from scripts or rc files
+    persist_functions: bool,
+    saved_functions: HashSet<String>,
     pub last_suggestion: Option<String>,
     pub last_error: Option<(String, String)>, // (command, stderr)
 }
@@ -20,7 +27,11 @@ pub struct MacroManager {
 impl MacroManager {
     pub fn new() -> Self {
         let home = dirs::home_dir().unwrap_or_else(|| std::path::PathBuf::from("."));
-        let chev_dir = home.join(".chev");
+        Self::new_at_path(home.join(".chev"))
+    }
+
+    /// A manager that keeps its files in `chev_dir` instead of `~/.chev`.
+    pub fn new_at_path(chev_dir: std::path::PathBuf) -> Self {
         if !chev_dir.exists() {
             let _ = std::fs::create_dir_all(&chev_dir);
         }
@@ -29,12 +40,17 @@ impl MacroManager {
         let mut manager = Self {
             macros: HashMap::new(),
             abbreviations: HashMap::new(),
+            functions: HashMap::new(),
             config_path: config_path.clone(),
+            functions_path: chev_dir.join("functions.json"),
+            persist_functions: false,
+            saved_functions: HashSet::new(),
             last_suggestion: None,
             last_error: None,
         };
 
         let _ = manager.load();
+        let _ = manager.load_functions();
         manager
     }
 
@@ -103,6 +119,67 @@ impl MacroManager {
     pub fn list(&self) -> &HashMap<String, Macro> {
         &self.macros
     }
+
+    /// Defines or replaces a function, saving it for later sessions if `set_persist_functions` is on.
+    pub fn define_function(&mut self, name: String, body: CommandList) -> Result<()> {
+        self.functions.insert(name.clone(), body);
+        if !self.persist_functions {
+            return Ok(());
+        }
+        self.saved_functions.insert(name);
+        self.save_functions()
+    }
+
+    pub fn unset_function(&mut self, name: &str) -> Result<()> {
+        self.functions.remove(name);
+        if !self.persist_functions {
+            return Ok(());
+        }
+        self.saved_functions.remove(name);
+        self.save_functions()
+    }
+
+    /// Turned on for the interactive prompt, and off again in subshells.
+    pub fn set_persist_functions(&mut self, persist: bool) {
+        self.persist_functions = persist;
+    }
+
+    pub fn function(&self, name: &str) -> Option<&CommandList> {
+        self.functions.get(name)
+    }
+
+    pub fn functions(&self) -> &HashMap<String, CommandList> {
+        &self.functions
+    }
+
+    /// Replaces every function with the ones a subshell was handed.
+    pub fn set_functions(&mut self, functions: HashMap<String, CommandList>) {
+        self.functions = functions;
+    }
+
+    fn save_functions(&self) -> Result<()> {
+        let sources: HashMap<&String, String> = self.functions.iter()
+            .filter(|(name, _)| self.saved_functions.contains(*name))
+            .map(|(name, body)| (name, body.to_string()))
+            .collect();
+        let json = serde_json::to_string_pretty(&sources)?;
+        fs::write(&self.functions_path, json).context("Failed to save functions")
+    }
+
+    fn load_functions(&mut self) -> Result<()> {
+        if self.functions_path.exists() {
+            let content = fs::read_to_string(&self.functions_path)?;
+            let sources: HashMap<String, String> = serde_json::from_str(&content)?;
+            for (name, source) in sources {
+                // A body that no longer parses is dropped rather than failing startup
+                if let Ok(body) = parse_input(&source) {
+                    self.saved_functions.insert(name.clone());
+                    self.functions.insert(name, body);
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -134,6 +211,26 @@ mod tests {
         assert_eq!(expanded, "echo all: one two three");
     }
 
+    #[test]
+    fn test_functions_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = MacroManager::new_at_path(dir.path().to_path_buf());
+        // A script's functions stay in memory
+        manager.define_function("helper".to_string(), parse_input("echo hi").unwrap()).unwrap();
+        assert!(MacroManager::new_at_path(dir.path().to_path_buf()).function("helper").is_none());
+
+        manager.set_persist_functions(true);
+        let body = parse_input("local dir=$1; if test -d \"$dir\"; then echo yes; else echo 'no'; fi").unwrap();
+        manager.define_function("isdir".to_string(), body.clone()).unwrap();
+
+        let reloaded = MacroManager::new_at_path(dir.path().to_path_buf());
+        assert_eq!(reloaded.function("isdir").map(|b| b.to_string()), Some(body.to_string()));
+        assert!(reloaded.function("helper").is_none());
+
+        manager.unset_function("isdir").unwrap();
+        assert!(MacroManager::new_at_path(dir.path().to_path_buf()).function("isdir").is_none());
+    }
+
     #[test]
     fn test_abbreviations() {
         let mut manager = MacroManager::new();
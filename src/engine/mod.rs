@@ -3,3 +3,11 @@ pub mod parser;
 pub mod jobs;
 pub mod env;
 pub mod macros;
+pub mod expand;
+pub mod glob;
+pub mod redirect;
+pub mod arith;
+pub mod dump;
+pub mod builtins;
+#[cfg(test)]
+pub(crate) mod test_support;
@@ -1,97 +1,1428 @@
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag},
-    character::complete::multispace0,
-    combinator::{map, opt},
-    multi::separated_list1,
-    sequence::delimited,
+    bytes::complete::{tag, take_till1, take_while},
+    character::complete::{anychar, char, digit1, multispace1, satisfy, space1},
+    combinator::{map, not, opt, recognize},
+    multi::{many0, separated_list1},
+    sequence::{pair, preceded, terminated},
     IResult,
     Parser,
 };
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Redirection {
-    Stdout(String),
-    Stderr(String),
-    Append(String),
-    StderrToStdout,
+    Stdout(Word),
+    Stderr(Word),
+    Append(Word),
+    /// `< file`
+    Stdin(Word),
+    /// `<<EOF`, with the body taken from the lines that follow
+    HereDoc(HereDoc),
+    /// `<<< word`, fed to stdin with a trailing newline
+    HereString(Word),
+    /// Any other `n< file`, `n> file` or `n>> file`
+    Fd { fd: i32, mode: FileMode, target: Word },
+    /// `n>&m` / `n<&m`: fd becomes a copy of target
+    Dup { fd: i32, target: i32 },
+    /// `n>&-`
+    Close(i32),
+    /// `&> file` / `&>> file`: stdout and stderr to the same file
+    Both { target: Word, append: bool },
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum FileMode {
+    Read,
+    Write,
+    Append,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct HereDoc {
+    pub delimiter: String,
+    /// A quoted delimiter (`<<'EOF'`) turns off expansion in the body
+    pub quoted: bool,
+    /// `<<-` strips leading tabs from the body and delimiter lines
+    pub strip_tabs: bool,
+    pub body: Word,
+}
+
+/// A shell word as written, kept in its quoted pieces until the executor expands it.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum WordPart {
+    /// Unquoted text
+    Literal(String),
+    /// Text inside '...', never expanded
+    SingleQuoted(String),
+    /// A backslash-escaped character, taken literally
+    Escaped(char),
+    /// Text inside "...", expanded but never split
+    DoubleQuoted(Vec<WordPart>),
+    /// $VAR / ${VAR...}
+    Param(ParamExpansion),
+    /// $(...) or `...`, replaced by the command's output
+    CommandSubst(CommandList),
+    /// $(( expr )). `$VAR` and `$(...)` inside expand before it's evaluated.
+    Arithmetic(Word),
+    /// <(...) or >(...), replaced by a /dev/fd path for a pipe to or from the list
+    ProcessSubst { list: CommandList, output: bool },
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ParamExpansion {
+    pub name: String,
+    pub op: ParamOp,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum ParamOp {
+    /// $VAR, ${VAR}
+    Plain,
+    /// ${#VAR}
+    Length,
+    /// ${VAR:-word}
+    Default(Word),
+    /// ${VAR:=word}
+    Assign(Word),
+    /// ${VAR%pattern}, ${VAR%%pattern}
+    RemoveSuffix { pattern: Word, longest: bool },
+    /// ${VAR#pattern}, ${VAR##pattern}
+    RemovePrefix { pattern: Word, longest: bool },
+}
+
+impl Word {
+    /// The word's text with quotes removed, if it contains no expansions.
+    pub fn as_literal(&self) -> Option<String> {
+        fn collect(parts: &[WordPart], out: &mut String) -> bool {
+            for part in parts {
+                match part {
+                    WordPart::Literal(s) | WordPart::SingleQuoted(s) => out.push_str(s),
+                    WordPart::Escaped(c) => out.push(*c),
+                    WordPart::DoubleQuoted(inner) => {
+                        if !collect(inner, out) {
+                            return false;
+                        }
+                    }
+                    WordPart::Param(_) | WordPart::CommandSubst(_) | WordPart::Arithmetic(_) | WordPart::ProcessSubst { .. } => return false,
+                }
+            }
+            true
+        }
+
+        let mut out = String::new();
+        if collect(&self.parts, &mut out) { Some(out) } else { None }
+    }
+}
+
+impl From<&str> for Word {
+    fn from(s: &str) -> Self {
+        Word { parts: vec![WordPart::Literal(s.to_string())] }
+    }
+}
+
+impl PartialEq<&str> for Word {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_literal().as_deref() == Some(*other)
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WordPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WordPart::Literal(s) => write!(f, "{}", s),
+            WordPart::SingleQuoted(s) => write!(f, "'{}'", s),
+            WordPart::Escaped(c) => write!(f, "\\{}", c),
+            WordPart::DoubleQuoted(inner) => {
+                write!(f, "\"")?;
+                for part in inner {
+                    write!(f, "{}", part)?;
+                }
+                write!(f, "\"")
+            }
+            WordPart::Param(p) => write!(f, "{}", p),
+            WordPart::CommandSubst(list) => write!(f, "$({})", list),
+            WordPart::Arithmetic(expr) => write!(f, "$(({}))", expr),
+            WordPart::ProcessSubst { list, output } => write!(f, "{}({})", if *output { '>' } else { '<' }, list),
+        }
+    }
+}
+
+impl fmt::Display for ParamExpansion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.op {
+            ParamOp::Plain => write!(f, "${{{}}}", self.name),
+            ParamOp::Length => write!(f, "${{#{}}}", self.name),
+            ParamOp::Default(w) => write!(f, "${{{}:-{}}}", self.name, w),
+            ParamOp::Assign(w) => write!(f, "${{{}:={}}}", self.name, w),
+            ParamOp::RemoveSuffix { pattern, longest } => {
+                let op = if *longest { "%%" } else { "%" };
+                write!(f, "${{{}{}{}}}", self.name, op, pattern)
+            }
+            ParamOp::RemovePrefix { pattern, longest } => {
+                let op = if *longest { "##" } else { "#" };
+                write!(f, "${{{}{}{}}}", self.name, op, pattern)
+            }
+        }
+    }
+}
+
+/// `NAME=value` before a command's name, or on its own to set a shell variable.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Command {
-    pub args: Vec<String>,
+    pub assignments: Vec<Assignment>,
+    pub args: Vec<Word>,
     pub redirections: Vec<Redirection>,
+    /// Set for `if`, `while`, `for` and `case`, which have no args of their own
+    pub compound: Option<Box<Compound>>,
+}
+
+/// Commands built from other command lists. Conditions are decided by exit status.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Compound {
+    /// `if c; then ...; elif c; then ...; else ...; fi`
+    If { branches: Vec<(CommandList, CommandList)>, else_branch: Option<CommandList> },
+    /// `while c; do ...; done`, or `until` when `until` is set
+    While { condition: CommandList, body: CommandList, until: bool },
+    /// `for name in words; do ...; done`. Without `in`, the words are `"$@"`.
+    For { var: String, words: Vec<Word>, body: CommandList },
+    /// `case word in pattern|pattern) ...;; esac`
+    Case { word: Word, arms: Vec<CaseArm> },
+    /// `name() { ...; }` or `fn name { ...; }`, which defines rather than runs the body
+    Function { name: String, body: CommandList },
+    /// `( ... )`, run in a separate shell process so `cd` and variables don't leak out
+    Subshell(CommandList),
+    /// `{ ...; }`, run in the current shell
+    Group(CommandList),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CaseArm {
+    pub patterns: Vec<Word>,
+    pub body: CommandList,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Pipeline {
     pub commands: Vec<Command>,
     pub background: bool,
 }
 
-fn parse_argument(input: &str) -> IResult<&str, String> {
-    alt((
-        // Quoted string
-        map(
-            delimited(tag("\""), is_not("\""), tag("\"")),
-            |s: &str| s.to_string(),
-        ),
-        // Simple word
-        map(is_not(" |><&"), |s: &str| s.to_string()),
+/// Pipelines joined by `;`, `&&` and `||`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum CommandList {
+    Pipeline(Pipeline),
+    /// `left && right`: right only runs if left succeeded
+    And(Box<CommandList>, Box<CommandList>),
+    /// `left || right`: right only runs if left failed
+    Or(Box<CommandList>, Box<CommandList>),
+    /// `a; b; c`: run in order regardless of status
+    Sequence(Vec<CommandList>),
+}
+
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirection::Stdout(w) => write!(f, "> {}", w),
+            Redirection::Stderr(w) => write!(f, "2> {}", w),
+            Redirection::Append(w) => write!(f, ">> {}", w),
+            Redirection::Stdin(w) => write!(f, "< {}", w),
+            Redirection::HereDoc(doc) => {
+                let op = if doc.strip_tabs { "<<-" } else { "<<" };
+                if doc.quoted {
+                    write!(f, "{}'{}'", op, doc.delimiter)
+                } else {
+                    write!(f, "{}{}", op, doc.delimiter)
+                }
+            }
+            Redirection::HereString(w) => write!(f, "<<< {}", w),
+            Redirection::Fd { fd, mode, target } => {
+                let op = match mode {
+                    FileMode::Read => "<",
+                    FileMode::Write => ">",
+                    FileMode::Append => ">>",
+                };
+                write!(f, "{}{} {}", fd, op, target)
+            }
+            Redirection::Dup { fd, target } => write!(f, "{}>&{}", fd, target),
+            Redirection::Close(fd) => write!(f, "{}>&-", fd),
+            Redirection::Both { target, append } => write!(f, "{} {}", if *append { "&>>" } else { "&>" }, target),
+        }
+    }
+}
+
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let compound = self.compound.iter().map(|c| c.to_string());
+        let assignments = self.assignments.iter().map(|a| a.to_string());
+        let words = assignments.chain(compound).chain(self.args.iter().map(|w| w.to_string()));
+        let redirections = self.redirections.iter().map(|r| r.to_string());
+        write!(f, "{}", words.chain(redirections).collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// Writes a list followed by the separator a keyword needs after it.
+fn write_body(f: &mut fmt::Formatter<'_>, list: &CommandList) -> fmt::Result {
+    if ends_in_background(list) { write!(f, "{} ", list) } else { write!(f, "{}; ", list) }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compound::If { branches, else_branch } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    write!(f, "{} ", if i == 0 { "if" } else { "elif" })?;
+                    write_body(f, condition)?;
+                    write!(f, "then ")?;
+                    write_body(f, body)?;
+                }
+                if let Some(body) = else_branch {
+                    write!(f, "else ")?;
+                    write_body(f, body)?;
+                }
+                write!(f, "fi")
+            }
+            Compound::While { condition, body, until } => {
+                write!(f, "{} ", if *until { "until" } else { "while" })?;
+                write_body(f, condition)?;
+                write!(f, "do ")?;
+                write_body(f, body)?;
+                write!(f, "done")
+            }
+            Compound::For { var, words, body } => {
+                write!(f, "for {} in", var)?;
+                for word in words {
+                    write!(f, " {}", word)?;
+                }
+                write!(f, "; do ")?;
+                write_body(f, body)?;
+                write!(f, "done")
+            }
+            Compound::Case { word, arms } => {
+                write!(f, "case {} in ", word)?;
+                for arm in arms {
+                    let patterns: Vec<String> = arm.patterns.iter().map(|p| p.to_string()).collect();
+                    write!(f, "{}) {};; ", patterns.join("|"), arm.body)?;
+                }
+                write!(f, "esac")
+            }
+            Compound::Function { name, body } => {
+                write!(f, "{}() {{ ", name)?;
+                write_body(f, body)?;
+                write!(f, "}}")
+            }
+            Compound::Subshell(list) => write!(f, "( {} )", list),
+            Compound::Group(list) => {
+                write!(f, "{{ ")?;
+                write_body(f, list)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", commands.join(" | "))?;
+        if self.background {
+            write!(f, " &")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CommandList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandList::Pipeline(p) => write!(f, "{}", p),
+            CommandList::And(l, r) => write!(f, "{} && {}", l, r),
+            CommandList::Or(l, r) => write!(f, "{} || {}", l, r),
+            CommandList::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        // A trailing '&' already separates the previous item
+                        let sep = if ends_in_background(&items[i - 1]) { " " } else { "; " };
+                        write!(f, "{}", sep)?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn is_word_delimiter(c: char) -> bool {
+    c.is_whitespace() || "|><&;()".contains(c)
+}
+
+fn at_command_end(input: &str) -> bool {
+    input.is_empty() || input.starts_with(['|', ';', ')', '\n']) || (input.starts_with('&') && !input.starts_with("&>"))
+}
+
+/// Reserved words that end a command list inside a compound command.
+const LIST_TERMINATORS: &[&str] = &["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+/// True if `input` starts with the reserved word `word` standing on its own.
+fn at_keyword(input: &str, word: &str) -> bool {
+    input.strip_prefix(word).is_some_and(|rest| rest.chars().next().is_none_or(is_word_delimiter))
+}
+
+fn keyword<'a>(word: &'static str) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> {
+    move |input: &'a str| {
+        if at_keyword(input, word) {
+            Ok((&input[word.len()..], &input[..word.len()]))
+        } else {
+            Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)))
+        }
+    }
+}
+
+fn at_list_end(input: &str) -> bool {
+    input.is_empty() || input.starts_with(')') || input.starts_with(";;") || LIST_TERMINATORS.iter().any(|w| at_keyword(input, w))
+}
+
+/// Spaces, tabs, `\<newline>` line continuations and comments between words.
+fn blank(input: &str) -> IResult<&str, &str> {
+    recognize(many0(alt((space1, tag("\\\n"), comment)))).parse(input)
+}
+
+/// Like `blank`, but also skips newlines, e.g. after `|` or `&&`.
+fn blank_lines(input: &str) -> IResult<&str, &str> {
+    recognize(many0(alt((multispace1, tag("\\\n"), comment)))).parse(input)
+}
+
+/// A `#` comment where a word would start, up to the end of the line.
+fn comment(input: &str) -> IResult<&str, &str> {
+    recognize(pair(char('#'), take_while(|c| c != '\n'))).parse(input)
+}
+
+fn parse_name(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        satisfy(|c: char| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
     )).parse(input)
 }
 
+fn parse_special_param(input: &str) -> IResult<&str, &str> {
+    recognize(satisfy(|c| "?$#@*!0123456789".contains(c))).parse(input)
+}
+
+/// The operand of `${VAR:-...}` and friends: runs until the closing brace.
+fn parse_brace_operand(input: &str) -> IResult<&str, Word> {
+    let mut parts = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() && !rest.starts_with('}') {
+        let (next, part) = alt((
+            parse_double_quoted,
+            parse_single_quoted,
+            parse_arithmetic,
+            parse_command_subst,
+            parse_param,
+            map(take_till1(|c| "}$\"'`".contains(c)), |s: &str| WordPart::Literal(s.to_string())),
+            map(tag("$"), |s: &str| WordPart::Literal(s.to_string())),
+        )).parse(rest)?;
+        parts.push(part);
+        rest = next;
+    }
+    Ok((rest, Word { parts }))
+}
+
+fn parse_braced_param(input: &str) -> IResult<&str, ParamExpansion> {
+    let (input, _) = tag("${")(input)?;
+
+    // ${#VAR} is a length, while a bare ${#} is the positional count
+    if let Ok((rest, (_, name, _))) = (char('#'), alt((parse_name, digit1, parse_special_param)), char('}')).parse(input) {
+        return Ok((rest, ParamExpansion { name: name.to_string(), op: ParamOp::Length }));
+    }
+
+    // Unlike `$10`, which is `$1` then a 0, `${10}` is the tenth positional
+    let (input, name) = alt((parse_name, digit1, parse_special_param)).parse(input)?;
+    let (input, op) = alt((
+        map(tag("}"), |_| ParamOp::Plain),
+        map((tag(":-"), parse_brace_operand, char('}')), |(_, w, _)| ParamOp::Default(w)),
+        map((tag(":="), parse_brace_operand, char('}')), |(_, w, _)| ParamOp::Assign(w)),
+        map((tag("%%"), parse_brace_operand, char('}')), |(_, w, _)| ParamOp::RemoveSuffix { pattern: w, longest: true }),
+        map((tag("%"), parse_brace_operand, char('}')), |(_, w, _)| ParamOp::RemoveSuffix { pattern: w, longest: false }),
+        map((tag("##"), parse_brace_operand, char('}')), |(_, w, _)| ParamOp::RemovePrefix { pattern: w, longest: true }),
+        map((tag("#"), parse_brace_operand, char('}')), |(_, w, _)| ParamOp::RemovePrefix { pattern: w, longest: false }),
+    )).parse(input)?;
+
+    Ok((input, ParamExpansion { name: name.to_string(), op }))
+}
+
+fn parse_param(input: &str) -> IResult<&str, WordPart> {
+    let simple = map(
+        (char('$'), alt((parse_name, parse_special_param))),
+        |(_, name): (char, &str)| ParamExpansion { name: name.to_string(), op: ParamOp::Plain },
+    );
+    map(alt((parse_braced_param, simple)), WordPart::Param).parse(input)
+}
+
+/// `$(list)`, parsed recursively so nested quotes and substitutions work.
+fn parse_dollar_paren(input: &str) -> IResult<&str, WordPart> {
+    let (input, _) = tag("$(")(input)?;
+    let (input, list) = commit(parse_command_list(input))?;
+    let (input, _) = commit(char(')')(input))?;
+    Ok((input, WordPart::CommandSubst(list)))
+}
+
+/// `` `list` ``: backslash only escapes `$`, `` ` `` and `\` inside, then the body is parsed like `$(...)`.
+fn parse_backtick(input: &str) -> IResult<&str, WordPart> {
+    let (mut rest, _) = char('`')(input)?;
+    let mut body = String::new();
+    loop {
+        let mut chars = rest.chars();
+        match chars.next() {
+            Some('`') => {
+                rest = chars.as_str();
+                break;
+            }
+            Some('\\') if chars.as_str().starts_with(['$', '`', '\\']) => {
+                body.extend(chars.next());
+                rest = chars.as_str();
+            }
+            Some(c) => {
+                body.push(c);
+                rest = chars.as_str();
+            }
+            None => return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char))),
+        }
+    }
+
+    match parse_command_list(&body) {
+        Ok(("", list)) => Ok((rest, WordPart::CommandSubst(list))),
+        _ => Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify))),
+    }
+}
+
+/// `$(( expr ))`, up to the `))` that balances the opening parentheses.
+/// Anything else starting with `$((`, like `$( (cd x; ls) )`, is left to `parse_command_subst`.
+fn parse_arithmetic(input: &str) -> IResult<&str, WordPart> {
+    let (mut rest, _) = tag("$((")(input)?;
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    loop {
+        if depth == 0 && let Some(next) = rest.strip_prefix("))") {
+            return Ok((next, WordPart::Arithmetic(Word { parts })));
+        }
+        let (next, part) = match rest.chars().next() {
+            None => return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char))),
+            Some('(') => {
+                depth += 1;
+                (&rest[1..], WordPart::Literal("(".to_string()))
+            }
+            Some(')') if depth == 0 => return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char))),
+            Some(')') => {
+                depth -= 1;
+                (&rest[1..], WordPart::Literal(")".to_string()))
+            }
+            Some(_) => alt((
+                parse_arithmetic,
+                parse_command_subst,
+                parse_param,
+                map(take_till1(|c| "()$".contains(c)), |s: &str| WordPart::Literal(s.to_string())),
+                map(tag("$"), |s: &str| WordPart::Literal(s.to_string())),
+            )).parse(rest)?,
+        };
+        parts.push(part);
+        rest = next;
+    }
+}
+
+/// `<(list)` or `>(list)`.
+fn parse_process_subst(input: &str) -> IResult<&str, WordPart> {
+    let (input, direction) = alt((tag("<("), tag(">("))).parse(input)?;
+    let (input, list) = commit(parse_command_list(input))?;
+    let (input, _) = commit(char(')')(input))?;
+    Ok((input, WordPart::ProcessSubst { list, output: direction == ">(" }))
+}
+
+fn parse_command_subst(input: &str) -> IResult<&str, WordPart> {
+    alt((parse_dollar_paren, parse_backtick)).parse(input)
+}
+
+fn parse_single_quoted(input: &str) -> IResult<&str, WordPart> {
+    let (input, _) = char('\'')(input)?;
+    let (input, content) = take_while(|c| c != '\'')(input)?;
+    let (input, _) = char('\'')(input)?;
+    Ok((input, WordPart::SingleQuoted(content.to_string())))
+}
+
+fn parse_double_quoted(input: &str) -> IResult<&str, WordPart> {
+    let (mut rest, _) = char('"')(input)?;
+    let mut parts = Vec::new();
+    loop {
+        if let Ok((next, _)) = char::<&str, nom::error::Error<&str>>('"')(rest) {
+            return Ok((next, WordPart::DoubleQuoted(parts)));
+        }
+        if let Some(next) = rest.strip_prefix("\\\n") {
+            rest = next;
+            continue;
+        }
+        let (next, part) = alt((
+            parse_arithmetic,
+            parse_command_subst,
+            parse_param,
+            // Inside double quotes a backslash only escapes $ ` " and itself
+            map(preceded(char('\\'), satisfy(|c| "$`\"\\".contains(c))), WordPart::Escaped),
+            map(take_till1(|c| "\"$`\\".contains(c)), |s: &str| WordPart::Literal(s.to_string())),
+            map(alt((tag("$"), tag("\\"))), |s: &str| WordPart::Literal(s.to_string())),
+        )).parse(rest)?;
+        parts.push(part);
+        rest = next;
+    }
+}
+
+/// `\x` outside quotes: the next character loses any special meaning.
+fn parse_escape(input: &str) -> IResult<&str, WordPart> {
+    map(preceded(char('\\'), anychar), WordPart::Escaped).parse(input)
+}
+
+fn parse_argument(input: &str) -> IResult<&str, Word> {
+    let mut parts = Vec::new();
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if is_word_delimiter(c) && !rest.starts_with("<(") && !rest.starts_with(">(") {
+            break;
+        }
+        if let Some(next) = rest.strip_prefix("\\\n") {
+            rest = next;
+            continue;
+        }
+        let (next, part) = alt((
+            parse_process_subst,
+            parse_double_quoted,
+            parse_single_quoted,
+            parse_arithmetic,
+            parse_command_subst,
+            parse_param,
+            parse_escape,
+            map(
+                take_till1(|c| is_word_delimiter(c) || "$\"'`\\".contains(c)),
+                |s: &str| WordPart::Literal(s.to_string()),
+            ),
+            // A '$' that doesn't start an expansion, or a trailing '\', is taken as-is
+            map(alt((tag("$"), tag("\\"))), |s: &str| WordPart::Literal(s.to_string())),
+        )).parse(rest)?;
+        parts.push(part);
+        rest = next;
+    }
+
+    if parts.is_empty() {
+        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Alpha)));
+    }
+    Ok((rest, Word { parts }))
+}
+
 fn parse_redirection(input: &str) -> IResult<&str, Redirection> {
     alt((
-        map(tag("2>&1"), |_| Redirection::StderrToStdout),
-        map((tag(">>"), multispace0, parse_argument), |(_, _, file)| {
-            Redirection::Append(file)
-        }),
-        map((tag("2>"), multispace0, parse_argument), |(_, _, file)| {
-            Redirection::Stderr(file)
-        }),
-        map((tag(">"), multispace0, parse_argument), |(_, _, file)| {
-            Redirection::Stdout(file)
-        }),
+        map((tag("&>>"), blank, parse_argument), |(_, _, target)| Redirection::Both { target, append: true }),
+        map((tag("&>"), blank, parse_argument), |(_, _, target)| Redirection::Both { target, append: false }),
+        map((tag("<<<"), blank, parse_argument), |(_, _, word)| Redirection::HereString(word)),
+        parse_heredoc,
+        parse_fd_redirection,
     )).parse(input)
 }
 
+/// `<<EOF` / `<<-EOF`. The body is filled in afterwards by `parse_input`.
+fn parse_heredoc(input: &str) -> IResult<&str, Redirection> {
+    let (input, _) = tag("<<")(input)?;
+    let (input, strip_tabs) = opt(char('-')).parse(input)?;
+    let (input, _) = blank(input)?;
+    let (input, word) = parse_argument(input)?;
+    let quoted = word.parts.iter().any(|p| !matches!(p, WordPart::Literal(_)));
+    let delimiter = word.parts.iter().map(|p| match p {
+        WordPart::Literal(s) | WordPart::SingleQuoted(s) => s.clone(),
+        WordPart::Escaped(c) => c.to_string(),
+        WordPart::DoubleQuoted(inner) => inner.iter().map(|p| p.to_string()).collect(),
+        other => other.to_string(),
+    }).collect();
+    Ok((input, Redirection::HereDoc(HereDoc { delimiter, quoted, strip_tabs: strip_tabs.is_some(), body: Word::default() })))
+}
+
+/// A descriptor number. One too big to be a descriptor at all is a syntax error.
+fn fd_number(input: &str) -> IResult<&str, i32> {
+    let (rest, digits) = digit1(input)?;
+    match digits.parse() {
+        Ok(fd) => Ok((rest, fd)),
+        Err(_) => Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::TooLarge))),
+    }
+}
+
+/// `[n]<`, `[n]>`, `[n]>>` to a file and `[n]>&m` / `[n]>&-` on descriptors.
+fn parse_fd_redirection(input: &str) -> IResult<&str, Redirection> {
+    let start = input;
+    let (input, digits) = opt(digit1).parse(input)?;
+    let (input, op) = alt((tag(">>"), tag(">&"), tag("<&"), tag(">"), tag("<"))).parse(input)?;
+    let fd = match digits {
+        Some(_) => fd_number(start)?.1,
+        None if op.starts_with('<') => 0,
+        None => 1,
+    };
+
+    if op.ends_with('&') {
+        return alt((
+            map(char('-'), move |_| Redirection::Close(fd)),
+            map(fd_number, move |target| Redirection::Dup { fd, target }),
+        )).parse(input);
+    }
+
+    let (input, _) = blank(input)?;
+    let (input, target) = parse_argument(input)?;
+    let red = match (op, fd) {
+        (">", 1) => Redirection::Stdout(target),
+        (">", 2) => Redirection::Stderr(target),
+        (">>", 1) => Redirection::Append(target),
+        ("<", 0) => Redirection::Stdin(target),
+        (">", fd) => Redirection::Fd { fd, mode: FileMode::Write, target },
+        (">>", fd) => Redirection::Fd { fd, mode: FileMode::Append, target },
+        (_, fd) => Redirection::Fd { fd, mode: FileMode::Read, target },
+    };
+    Ok((input, red))
+}
+
+/// Once a compound's opening keyword is seen, a failure is a syntax error rather than a cue to try something else.
+fn commit<T>(result: IResult<&str, T>) -> IResult<&str, T> {
+    result.map_err(|e| match e {
+        nom::Err::Error(e) => nom::Err::Failure(e),
+        e => e,
+    })
+}
+
+fn parse_compound(input: &str) -> IResult<&str, Compound> {
+    if at_keyword(input, "if") {
+        commit(parse_if(input))
+    } else if at_keyword(input, "while") || at_keyword(input, "until") {
+        commit(parse_while(input))
+    } else if at_keyword(input, "for") {
+        commit(parse_for(input))
+    } else if at_keyword(input, "case") {
+        commit(parse_case(input))
+    } else if at_keyword(input, "fn") {
+        commit(parse_fn(input))
+    } else if input.starts_with('(') {
+        commit(parse_subshell(input))
+    } else if at_keyword(input, "{") {
+        commit(map(parse_brace_group, Compound::Group).parse(input))
+    } else if let Ok((rest, name)) = terminated(parse_name, (blank, char('('), blank, char(')'))).parse(input) {
+        let (rest, body) = commit(preceded(blank_lines, parse_brace_group).parse(rest))?;
+        Ok((rest, Compound::Function { name: name.to_string(), body }))
+    } else {
+        Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)))
+    }
+}
+
+fn parse_if(input: &str) -> IResult<&str, Compound> {
+    let (mut input, _) = keyword("if")(input)?;
+    let mut branches = Vec::new();
+    loop {
+        let (rest, condition) = parse_command_list(input)?;
+        let (rest, _) = keyword("then")(rest)?;
+        let (rest, body) = parse_command_list(rest)?;
+        branches.push((condition, body));
+        match keyword("elif")(rest) {
+            Ok((rest, _)) => input = rest,
+            Err(_) => {
+                input = rest;
+                break;
+            }
+        }
+    }
+    let (input, else_branch) = opt(preceded(keyword("else"), parse_command_list)).parse(input)?;
+    let (input, _) = keyword("fi")(input)?;
+    Ok((input, Compound::If { branches, else_branch }))
+}
+
+fn parse_while(input: &str) -> IResult<&str, Compound> {
+    let (input, word) = alt((keyword("while"), keyword("until"))).parse(input)?;
+    let (input, condition) = parse_command_list(input)?;
+    let (input, _) = keyword("do")(input)?;
+    let (input, body) = parse_command_list(input)?;
+    let (input, _) = keyword("done")(input)?;
+    Ok((input, Compound::While { condition, body, until: word == "until" }))
+}
+
+fn parse_for(input: &str) -> IResult<&str, Compound> {
+    let (input, _) = keyword("for")(input)?;
+    let (input, _) = blank(input)?;
+    let (input, var) = parse_name(input)?;
+    let (input, _) = blank_lines(input)?;
+
+    let (input, words) = if let Ok((mut rest, _)) = keyword("in")(input) {
+        let mut words = Vec::new();
+        loop {
+            let (next, _) = blank(rest)?;
+            rest = next;
+            let Ok((next, word)) = parse_argument(rest) else { break };
+            words.push(word);
+            rest = next;
+        }
+        let (rest, _) = alt((char(';'), char('\n'))).parse(rest)?;
+        (rest, words)
+    } else {
+        let (rest, _) = opt(char(';')).parse(input)?;
+        let all_args = WordPart::Param(ParamExpansion { name: "@".to_string(), op: ParamOp::Plain });
+        (rest, vec![Word { parts: vec![WordPart::DoubleQuoted(vec![all_args])] }])
+    };
+
+    let (input, _) = blank_lines(input)?;
+    let (input, _) = keyword("do")(input)?;
+    let (input, body) = parse_command_list(input)?;
+    let (input, _) = keyword("done")(input)?;
+    Ok((input, Compound::For { var: var.to_string(), words, body }))
+}
+
+fn parse_case(input: &str) -> IResult<&str, Compound> {
+    let (input, _) = keyword("case")(input)?;
+    let (input, _) = blank(input)?;
+    let (input, word) = parse_argument(input)?;
+    let (input, _) = blank_lines(input)?;
+    let (mut input, _) = keyword("in")(input)?;
+
+    let mut arms = Vec::new();
+    loop {
+        let (rest, _) = blank_lines(input)?;
+        if let Ok((rest, _)) = keyword("esac")(rest) {
+            return Ok((rest, Compound::Case { word, arms }));
+        }
+        let (rest, _) = opt(char('(')).parse(rest)?;
+        let (rest, _) = blank(rest)?;
+        let (rest, patterns) = separated_list1((blank, char('|'), blank), parse_argument).parse(rest)?;
+        let (rest, _) = (blank, char(')')).parse(rest)?;
+        let (rest, body) = parse_command_list(rest)?;
+        // The last arm may leave out its `;;`
+        let (rest, _) = opt(tag(";;")).parse(rest)?;
+        arms.push(CaseArm { patterns, body });
+        input = rest;
+    }
+}
+
+/// `fn name { list; }`
+fn parse_fn(input: &str) -> IResult<&str, Compound> {
+    let (input, _) = keyword("fn")(input)?;
+    let (input, _) = blank(input)?;
+    let (input, name) = parse_name(input)?;
+    let (input, _) = opt((blank, char('('), blank, char(')'))).parse(input)?;
+    let (input, _) = blank_lines(input)?;
+    let (input, body) = parse_brace_group(input)?;
+    Ok((input, Compound::Function { name: name.to_string(), body }))
+}
+
+fn parse_subshell(input: &str) -> IResult<&str, Compound> {
+    let (input, _) = char('(')(input)?;
+    let (input, list) = parse_command_list(input)?;
+    let (input, _) = char(')')(input)?;
+    Ok((input, Compound::Subshell(list)))
+}
+
+/// `{ list; }`. Like `}`, the `{` only counts as a word of its own.
+fn parse_brace_group(input: &str) -> IResult<&str, CommandList> {
+    let (input, _) = keyword("{")(input)?;
+    let (input, list) = parse_command_list(input)?;
+    let (input, _) = keyword("}")(input)?;
+    Ok((input, list))
+}
+
 fn parse_single_command(input: &str) -> IResult<&str, Command> {
-    let (input, _) = multispace0(input)?;
+    let (input, _) = blank(input)?;
+
+    // Reserved words only count in command position
+    match parse_compound(input) {
+        Ok((mut rest, compound)) => {
+            // Redirections after the closing keyword apply to the whole compound
+            let mut redirections = Vec::new();
+            loop {
+                let (next, _) = blank(rest)?;
+                rest = next;
+                let Ok((next, red)) = parse_redirection(rest) else { break };
+                redirections.push(red);
+                rest = next;
+            }
+            return Ok((rest, Command { assignments: Vec::new(), args: Vec::new(), redirections, compound: Some(Box::new(compound)) }));
+        }
+        Err(nom::Err::Error(_)) => {}
+        Err(e) => return Err(e),
+    }
+
     let mut current_input = input;
+    let mut assignments = Vec::new();
     let mut args = Vec::new();
     let mut redirections = Vec::new();
 
-    while !current_input.is_empty() && !current_input.starts_with('|') && !current_input.starts_with('&') {
-        let (next_input, _) = multispace0(current_input)?;
+    while !at_command_end(current_input) {
+        let (next_input, _) = blank(current_input)?;
         current_input = next_input;
 
-        if current_input.is_empty() || current_input.starts_with('|') || current_input.starts_with('&') {
+        if at_command_end(current_input) {
             break;
         }
 
-        if let Ok((next_input, red)) = parse_redirection(current_input) {
+        let redirection = parse_redirection(current_input);
+        // e.g. a descriptor number that's out of range
+        if let Err(nom::Err::Failure(e)) = redirection {
+            return Err(nom::Err::Failure(e));
+        }
+        if let Ok((next_input, red)) = redirection {
             redirections.push(red);
             current_input = next_input;
-        } else if let Ok((next_input, arg)) = parse_argument(current_input) {
-            args.push(arg);
+        } else if let (true, Ok((next_input, assignment))) = (args.is_empty(), parse_assignment(current_input)) {
+            // Only words before the command name are assignments
+            assignments.push(assignment);
             current_input = next_input;
         } else {
-            break;
+            match parse_argument(current_input) {
+                Ok((next_input, arg)) => {
+                    args.push(arg);
+                    current_input = next_input;
+                }
+                // e.g. a `$(` block that isn't closed yet
+                Err(nom::Err::Failure(e)) => return Err(nom::Err::Failure(e)),
+                Err(_) => break,
+            }
         }
     }
 
-    Ok((current_input, Command { args, redirections }))
+    if assignments.is_empty() && args.is_empty() && redirections.is_empty() {
+        // Nothing here but maybe an operator, e.g. the second `|` in `a | | b`
+        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+    }
+    Ok((current_input, Command { assignments, args, redirections, compound: None }))
+}
+
+fn parse_assignment(input: &str) -> IResult<&str, Assignment> {
+    let (input, name) = terminated(parse_name, char('=')).parse(input)?;
+    let (input, value) = opt(parse_argument).parse(input)?;
+    Ok((input, Assignment { name: name.to_string(), value: value.unwrap_or_default() }))
 }
 
 pub fn parse_pipeline(input: &str) -> IResult<&str, Pipeline> {
-    let (input, commands) = separated_list1((multispace0, tag("|"), multispace0), parse_single_command).parse(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, background_opt) = opt(tag("&")).parse(input)?;
-    
+    // A single '|' joins commands, while '||' belongs to the command list
+    let mut pipe = (blank, terminated(tag("|"), not(char('|'))), blank_lines);
+    let (mut input, first) = parse_single_command(input)?;
+    let mut commands = vec![first];
+    while let Ok((rest, _)) = pipe.parse(input) {
+        // A `|` has to be followed by a command, though it may be on the next line
+        let (rest, command) = commit(parse_single_command(rest))?;
+        commands.push(command);
+        input = rest;
+    }
+    let (input, _) = blank(input)?;
+    let (input, background_opt) = opt(terminated(tag("&"), not(char('&')))).parse(input)?;
+
     Ok((input, Pipeline { commands, background: background_opt.is_some() }))
 }
 
+/// `pipeline (&& pipeline | || pipeline)*`, grouped to the left like POSIX sh.
+fn parse_and_or(input: &str) -> IResult<&str, CommandList> {
+    let (mut input, first) = parse_pipeline(input)?;
+    let mut list = CommandList::Pipeline(first);
+
+    loop {
+        let (rest, _) = blank(input)?;
+        let op = if rest.starts_with("&&") {
+            "&&"
+        } else if rest.starts_with("||") {
+            "||"
+        } else {
+            break;
+        };
+
+        let (rest, _) = blank_lines(&rest[op.len()..])?;
+        let (rest, right) = parse_pipeline(rest)?;
+        let right = Box::new(CommandList::Pipeline(right));
+        list = if op == "&&" {
+            CommandList::And(Box::new(list), right)
+        } else {
+            CommandList::Or(Box::new(list), right)
+        };
+        input = rest;
+    }
+
+    Ok((input, list))
+}
+
+fn ends_in_background(list: &CommandList) -> bool {
+    match list {
+        CommandList::Pipeline(p) => p.background,
+        CommandList::And(_, right) | CommandList::Or(_, right) => ends_in_background(right),
+        CommandList::Sequence(items) => items.last().is_some_and(ends_in_background),
+    }
+}
+
+/// Parses a full command line: and-or lists separated by `;`, newlines or a trailing `&`.
+pub fn parse_command_list(input: &str) -> IResult<&str, CommandList> {
+    let mut items = Vec::new();
+    let (mut input, _) = blank_lines(input)?;
+
+    while !at_list_end(input) {
+        let (rest, item) = parse_and_or(input)?;
+        // `a & b` runs `a` in the background, so '&' also separates commands
+        let backgrounded = ends_in_background(&item);
+        items.push(item);
+
+        let (rest, _) = blank(rest)?;
+        // `;;` ends a case arm rather than separating commands
+        let (rest, sep) = opt(alt((terminated(char(';'), not(char(';'))), char('\n')))).parse(rest)?;
+        let (rest, _) = blank_lines(rest)?;
+        input = rest;
+        if sep.is_none() && !backgrounded {
+            break;
+        }
+    }
+
+    let list = if items.len() == 1 {
+        items.remove(0)
+    } else {
+        // Zero items (e.g. `$()`) is an empty sequence
+        CommandList::Sequence(items)
+    };
+    Ok((input, list))
+}
+
+/// Parses everything the user entered, including here-document bodies.
+/// All of it has to parse: anything left over is reported rather than dropped. After an error,
+/// parsing picks up again past the next `;` or newline, so later mistakes are reported too;
+/// one inside a compound command can be followed by another about its closing keyword.
+pub fn parse_input(input: &str) -> Result<CommandList, ParseErrors> {
+    let heredocs = extract_heredocs(input);
+    let text = heredocs.text.as_str();
+    let mut errors = Vec::new();
+    let mut from = 0;
+    loop {
+        let (rest, bad_fd) = match parse_command_list(&text[from..]) {
+            Ok(("", mut list)) if errors.is_empty() => {
+                fill_heredocs(&mut list, &mut heredocs.bodies.into_iter());
+                return Ok(list);
+            }
+            Ok(("", _)) => break,
+            Ok((rest, _)) => (rest, false),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => (e.input, e.code == nom::error::ErrorKind::TooLarge),
+            Err(nom::Err::Incomplete(_)) => ("", false),
+        };
+
+        // Whatever went wrong afterwards, an unclosed quote is the real cause
+        let (message, start, len) = match heredocs.open_quote {
+            Some(at) => ("unterminated quote".to_string(), at, 1),
+            None if bad_fd => {
+                let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                ("bad file descriptor".to_string(), text.len() - rest.len(), digits)
+            }
+            None => {
+                let at = text.len().saturating_sub(rest.len());
+                let token_start = at + (rest.len() - rest.trim_start_matches([' ', '\t']).len());
+                match next_token(&text[token_start.min(text.len())..]) {
+                    Some(token) => (format!("unexpected `{}`", token), token_start, token.len()),
+                    None => ("unexpected end of input".to_string(), text.trim_end().len(), 1),
+                }
+            }
+        };
+        let span_start = heredocs.input_offset(start).min(input.len());
+        errors.push(ParseError {
+            message,
+            span: span_start..(span_start + len).min(input.len()).max(span_start),
+            input: input.to_string(),
+        });
+        if heredocs.open_quote.is_some() {
+            break;
+        }
+
+        // Skipping whole tokens, so a `;` inside quotes isn't taken for a separator
+        let resume = tokens(text).find(|&(at, token)| at >= start + len && (token == ";" || token == "\n"));
+        match resume {
+            Some((at, _)) => from = at + 1,
+            None => break,
+        }
+    }
+    Err(ParseErrors { errors })
+}
+
+/// Operators, longest first, so a token is the whole of one.
+const OPERATORS: &[&str] = &[
+    "<<<", "<<-", "&>>", "&&", "||", ";;", "<<", ">>", "&>", ">&", "<&", "<(", ">(", "|", "&", ";", "(", ")", "<", ">",
+];
+
+/// The operator or word at the start of `input`, if there's anything but whitespace.
+fn next_token(input: &str) -> Option<&str> {
+    let input = input.trim_start_matches([' ', '\t']);
+    if input.is_empty() || input.trim().is_empty() {
+        return None;
+    }
+    if let Some(op) = OPERATORS.iter().find(|op| input.starts_with(**op)) {
+        return Some(&input[..op.len()]);
+    }
+    let len = input.find(is_word_delimiter).unwrap_or(input.len());
+    Some(&input[..len.max(input.chars().next().map_or(0, char::len_utf8))])
+}
+
+/// Splits input into the words and operators the parser works through, for `chev parse`.
+/// Words keep their quotes, and here-document bodies are left out.
+pub fn tokenize(input: &str) -> Vec<String> {
+    let text = extract_heredocs(input).text;
+    tokens(&text).map(|(_, token)| token.to_string()).collect()
+}
+
+/// The tokens of `text` with the offset each starts at.
+fn tokens(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        rest = blank(rest).map_or(rest, |(next, _)| next);
+        let c = rest.chars().next()?;
+        // `2>`, `3<&`: a redirection's fd number belongs to its operator
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let fd_op = OPERATORS.iter().find(|op| op.starts_with(['<', '>']) && !op.ends_with('(') && rest[digits..].starts_with(**op));
+        let len = if c == '\n' {
+            1
+        } else if let (true, Some(op)) = (digits > 0, fd_op) {
+            digits + op.len()
+        } else if let (false, Some(op)) = (rest.starts_with("<(") || rest.starts_with(">("), OPERATORS.iter().find(|op| rest.starts_with(**op))) {
+            op.len()
+        } else {
+            recognize(parse_argument).parse(rest).map_or(c.len_utf8(), |(_, word)| word.len())
+        };
+        let token = (text.len() - rest.len(), &rest[..len]);
+        rest = &rest[len..];
+        Some(token)
+    })
+}
+
+/// A syntax error, with the byte range of the input it's about.
+/// Displays as the message with the offending line underneath and the range marked with carets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: std::ops::Range<usize>,
+    input: String,
+}
+
+impl ParseError {
+    /// 1-based line and column of the start of the span.
+    pub fn position(&self) -> (usize, usize) {
+        let before = &self.input[..self.span.start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (line, _) = self.position();
+        let line_start = self.input[..self.span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.input[self.span.start..].find('\n').map_or(self.input.len(), |i| self.span.start + i);
+        let source = &self.input[line_start..line_end];
+
+        write!(f, "syntax error: {}", self.message)?;
+        if self.input.contains('\n') {
+            write!(f, " on line {}", line)?;
+        }
+        // Tabs stay tabs so the carets line up however the terminal expands them
+        let indent: String = source[..self.span.start - line_start].chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self.input[self.span.start..self.span.end.min(line_end).max(self.span.start)].chars().count().max(1);
+        write!(f, "\n  {}\n  {}{}", source, indent, "^".repeat(width))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Every syntax error found in the input, in order, each displayed on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseErrors {
+    pub errors: Vec<ParseError>,
+}
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+/// True while a here-document is still waiting for its delimiter line,
+/// or a compound command like `if` or `while` hasn't been closed yet.
+pub fn needs_more_input(input: &str) -> bool {
+    let heredocs = extract_heredocs(input);
+    if !heredocs.complete {
+        return true;
+    }
+    // Running out of input partway through is what an open block looks like
+    match parse_command_list(&heredocs.text) {
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => e.input.trim().is_empty(),
+        _ => false,
+    }
+}
+
+struct HereDocs {
+    /// The input with the body lines removed
+    text: String,
+    bodies: Vec<String>,
+    complete: bool,
+    /// (offset in `text`, bytes of input) for each run of body lines taken out
+    removed: Vec<(usize, usize)>,
+    /// Where in `text` a quote that's never closed starts
+    open_quote: Option<usize>,
+}
+
+impl HereDocs {
+    /// Maps an offset in `text` back to the original input.
+    fn input_offset(&self, offset: usize) -> usize {
+        offset + self.removed.iter().filter(|(at, _)| *at <= offset).map(|(_, len)| len).sum::<usize>()
+    }
+}
+
+/// Pulls here-document bodies out of the input so the rest can be parsed line by line.
+/// Bodies start after the newline that ends the `<<` line, in the order the operators appear.
+fn extract_heredocs(input: &str) -> HereDocs {
+    let mut text = String::new();
+    let mut bodies = Vec::new();
+    let mut complete = true;
+    // (delimiter, strip_tabs) for operators seen on the current line
+    let mut pending: Vec<(String, bool)> = Vec::new();
+    let (mut single, mut double) = (false, false);
+    let mut quote_start = 0;
+    let mut removed = Vec::new();
+    let mut rest = input;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' if !single => {
+                let len = 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                text.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+            '\'' if !double => {
+                single = !single;
+                quote_start = text.len();
+            }
+            '"' if !single => {
+                double = !double;
+                quote_start = text.len();
+            }
+            '#' if !single && !double && text.chars().last().is_none_or(|p| p.is_whitespace() || ";&|()".contains(p)) => {
+                // Quotes and `<<` in a comment mean nothing
+                let len = rest.find('\n').unwrap_or(rest.len());
+                text.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+            '$' if !single && rest.starts_with("$((") => {
+                // `<<` in `$(( 1 << 2 ))` is a shift
+                let len = arithmetic_len(rest);
+                text.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+            '<' if !single && !double && rest.starts_with("<<") && !rest.starts_with("<<<") => {
+                let strip_tabs = rest[2..].starts_with('-');
+                let op_len = if strip_tabs { 3 } else { 2 };
+                let after_op = &rest[op_len..];
+                let word_start = after_op.len() - after_op.trim_start_matches([' ', '\t']).len();
+                let (word_len, delimiter) = read_delimiter(&after_op[word_start..]);
+                let len = op_len + word_start + word_len;
+                text.push_str(&rest[..len]);
+                rest = &rest[len..];
+                pending.push((delimiter, strip_tabs));
+                continue;
+            }
+            '\n' if !single && !double && !pending.is_empty() => {
+                text.push('\n');
+                rest = &rest[1..];
+                let before = rest.len();
+                for (delimiter, strip_tabs) in pending.drain(..) {
+                    let mut body = String::new();
+                    let mut found = false;
+                    while !rest.is_empty() {
+                        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+                        rest = next;
+                        let line = if strip_tabs { line.trim_start_matches('\t') } else { line };
+                        if line == delimiter {
+                            found = true;
+                            break;
+                        }
+                        body.push_str(line);
+                        body.push('\n');
+                    }
+                    complete &= found;
+                    bodies.push(body);
+                }
+                removed.push((text.len(), before - rest.len()));
+                continue;
+            }
+            _ => {}
+        }
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if !pending.is_empty() {
+        complete = false;
+    }
+    let open_quote = (single || double).then_some(quote_start);
+    HereDocs { text, bodies, complete, removed, open_quote }
+}
+
+/// How much of `input`, which starts with `$((`, belongs to the arithmetic expansion.
+fn arithmetic_len(input: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// Reads a here-document delimiter word, returning its length in the input and its unquoted text.
+fn read_delimiter(input: &str) -> (usize, String) {
+    let mut delimiter = String::new();
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => delimiter.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => delimiter.extend(chars.next().map(|(_, c)| c)),
+            (None, c) if is_word_delimiter(c) => return (i, delimiter),
+            (None, c) => delimiter.push(c),
+        }
+    }
+    (input.len(), delimiter)
+}
+
+fn fill_heredocs(list: &mut CommandList, bodies: &mut impl Iterator<Item = String>) {
+    match list {
+        CommandList::Pipeline(pipeline) => {
+            for cmd in &mut pipeline.commands {
+                fill_command_heredocs(cmd, bodies);
+            }
+        }
+        CommandList::And(left, right) | CommandList::Or(left, right) => {
+            fill_heredocs(left, bodies);
+            fill_heredocs(right, bodies);
+        }
+        CommandList::Sequence(items) => {
+            for item in items {
+                fill_heredocs(item, bodies);
+            }
+        }
+    }
+}
+
+fn fill_command_heredocs(cmd: &mut Command, bodies: &mut impl Iterator<Item = String>) {
+    // A compound's inner commands come before its own redirections in the source
+    if let Some(compound) = &mut cmd.compound {
+        match compound.as_mut() {
+            Compound::If { branches, else_branch } => {
+                for (condition, body) in branches {
+                    fill_heredocs(condition, bodies);
+                    fill_heredocs(body, bodies);
+                }
+                if let Some(body) = else_branch {
+                    fill_heredocs(body, bodies);
+                }
+            }
+            Compound::While { condition, body, .. } => {
+                fill_heredocs(condition, bodies);
+                fill_heredocs(body, bodies);
+            }
+            Compound::For { body, .. } | Compound::Function { body, .. } => fill_heredocs(body, bodies),
+            Compound::Subshell(list) | Compound::Group(list) => fill_heredocs(list, bodies),
+            Compound::Case { arms, .. } => {
+                for arm in arms {
+                    fill_heredocs(&mut arm.body, bodies);
+                }
+            }
+        }
+    }
+
+    for red in &mut cmd.redirections {
+        if let Redirection::HereDoc(doc) = red {
+            let body = bodies.next().unwrap_or_default();
+            doc.body = if doc.quoted {
+                Word { parts: vec![WordPart::SingleQuoted(body)] }
+            } else {
+                parse_heredoc_body(&body)
+            };
+        }
+    }
+}
+
+/// An unquoted here-document body expands like a double-quoted string, except `"` is literal.
+fn parse_heredoc_body(body: &str) -> Word {
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        if let Some(next) = rest.strip_prefix("\\\n") {
+            rest = next;
+            continue;
+        }
+        let parsed: IResult<&str, WordPart> = alt((
+            parse_arithmetic,
+            parse_command_subst,
+            parse_param,
+            map(preceded(char('\\'), satisfy(|c| "$`\\".contains(c))), WordPart::Escaped),
+            map(take_till1(|c| "$`\\".contains(c)), |s: &str| WordPart::Literal(s.to_string())),
+        )).parse(rest);
+        let (next, part) = match parsed {
+            Ok(parsed) => parsed,
+            // Anything else, like a `$(` that's never closed, is just text in a body
+            Err(_) => {
+                let c = rest.chars().next().unwrap_or_default();
+                (&rest[c.len_utf8()..], WordPart::Literal(c.to_string()))
+            }
+        };
+        parts.push(part);
+        rest = next;
+    }
+    Word { parts }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,11 +1460,373 @@ mod tests {
         assert_eq!(pipeline.commands[0].redirections.len(), 2);
         assert_eq!(
             pipeline.commands[0].redirections[0],
-            Redirection::Stdout("out.txt".to_string())
+            Redirection::Stdout(Word::from("out.txt"))
         );
         assert_eq!(
             pipeline.commands[0].redirections[1],
-            Redirection::Stderr("err.txt".to_string())
+            Redirection::Stderr(Word::from("err.txt"))
+        );
+    }
+
+    fn pipeline_args(list: &CommandList) -> Vec<String> {
+        match list {
+            CommandList::Pipeline(p) => p.commands[0].args.iter().map(|w| w.to_string()).collect(),
+            other => panic!("expected a pipeline, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_command_list() {
+        let (rest, list) = parse_command_list("cargo build && cargo test || echo failed; ls").unwrap();
+        assert!(rest.is_empty());
+        let CommandList::Sequence(items) = list else { panic!("expected a sequence") };
+        assert_eq!(items.len(), 2);
+        assert_eq!(pipeline_args(&items[1]), vec!["ls"]);
+
+        // (build && test) || echo
+        let CommandList::Or(left, right) = &items[0] else { panic!("expected ||") };
+        assert_eq!(pipeline_args(right), vec!["echo", "failed"]);
+        let CommandList::And(build, test) = left.as_ref() else { panic!("expected &&") };
+        assert_eq!(pipeline_args(build), vec!["cargo", "build"]);
+        assert_eq!(pipeline_args(test), vec!["cargo", "test"]);
+    }
+
+    #[test]
+    fn test_parse_background_in_list() {
+        let (rest, list) = parse_command_list("sleep 10 & echo started;").unwrap();
+        assert!(rest.is_empty());
+        let CommandList::Sequence(items) = list else { panic!("expected a sequence") };
+        let CommandList::Pipeline(first) = &items[0] else { panic!("expected a pipeline") };
+        assert!(first.background);
+        assert_eq!(pipeline_args(&items[1]), vec!["echo", "started"]);
+    }
+
+    #[test]
+    fn test_parse_single_quotes_and_escapes() {
+        let (_, pipeline) = parse_pipeline("git commit -m 'fix bug'").unwrap();
+        assert_eq!(pipeline.commands[0].args, vec!["git", "commit", "-m", "fix bug"]);
+
+        let (_, pipeline) = parse_pipeline(r#"echo "a \"b\"" a\ b 'it''s' "\$HOME" "C:\dir""#).unwrap();
+        assert_eq!(pipeline.commands[0].args, vec!["echo", "a \"b\"", "a b", "its", "$HOME", "C:\\dir"]);
+    }
+
+    #[test]
+    fn test_parse_adjacent_quotes() {
+        let (_, pipeline) = parse_pipeline(r#"run --name="foo bar"x 'a'"b"c"#).unwrap();
+        assert_eq!(pipeline.commands[0].args, vec!["run", "--name=foo barx", "abc"]);
+    }
+
+    #[test]
+    fn test_parse_line_continuation() {
+        let (rest, pipeline) = parse_pipeline("cargo build \\\n  --release").unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(pipeline.commands[0].args, vec!["cargo", "build", "--release"]);
+    }
+
+    #[test]
+    fn test_parse_command_substitution() {
+        let (_, pipeline) = parse_pipeline("echo $(git rev-parse \"$REF\") \"at `date +%s`\"").unwrap();
+        let args = &pipeline.commands[0].args;
+        let WordPart::CommandSubst(CommandList::Pipeline(inner)) = &args[1].parts[0] else {
+            panic!("expected $(...), got {:?}", args[1]);
+        };
+        assert_eq!(inner.commands[0].args[..2], ["git", "rev-parse"]);
+        assert_eq!(args[2].to_string(), "\"at $(date +%s)\"");
+
+        // Nested substitutions keep their own quoting
+        let (rest, pipeline) = parse_pipeline("echo \"$(dirname \"$(pwd)\")\"").unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(pipeline.commands[0].args[1].to_string(), "\"$(dirname \"$(pwd)\")\"");
+    }
+
+    #[test]
+    fn test_parse_variables() {
+        let (_, pipeline) = parse_pipeline("echo $HOME \"dir: ${PWD}\" '$HOME'").unwrap();
+        let args = &pipeline.commands[0].args;
+        assert_eq!(args[1].parts, vec![WordPart::Param(ParamExpansion { name: "HOME".to_string(), op: ParamOp::Plain })]);
+        assert_eq!(
+            args[2].parts,
+            vec![WordPart::DoubleQuoted(vec![
+                WordPart::Literal("dir: ".to_string()),
+                WordPart::Param(ParamExpansion { name: "PWD".to_string(), op: ParamOp::Plain }),
+            ])]
+        );
+        assert_eq!(args[3], "$HOME");
+    }
+
+    #[test]
+    fn test_parse_param_operators() {
+        let (_, pipeline) = parse_pipeline("echo ${NAME:-anon} ${#NAME} ${FILE%.rs} ${FILE##*/}").unwrap();
+        let ops: Vec<_> = pipeline.commands[0].args[1..].iter().map(|w| match &w.parts[0] {
+            WordPart::Param(p) => p.op.clone(),
+            other => panic!("unexpected part {:?}", other),
+        }).collect();
+        assert_eq!(ops[0], ParamOp::Default(Word::from("anon")));
+        assert_eq!(ops[1], ParamOp::Length);
+        assert_eq!(ops[2], ParamOp::RemoveSuffix { pattern: Word::from(".rs"), longest: false });
+        assert_eq!(ops[3], ParamOp::RemovePrefix { pattern: Word::from("*/"), longest: true });
+
+        let (_, pipeline) = parse_pipeline("echo ${10} $10 ${#12} ${11:-none}").unwrap();
+        let names: Vec<_> = pipeline.commands[0].args[1..].iter().map(|w| match &w.parts[0] {
+            WordPart::Param(p) => p.name.clone(),
+            other => panic!("unexpected part {:?}", other),
+        }).collect();
+        assert_eq!(names, vec!["10", "1", "12", "11"]);
+    }
+
+    #[test]
+    fn test_parse_fd_redirections() {
+        let (_, pipeline) = parse_pipeline("cmd < in.txt 3> fd.log 2>>err.log >&2 4<&- &> both.log <<< \"$X\"").unwrap();
+        let reds = &pipeline.commands[0].redirections;
+        assert_eq!(pipeline.commands[0].args, vec!["cmd"]);
+        assert_eq!(reds[0], Redirection::Stdin(Word::from("in.txt")));
+        assert_eq!(reds[1], Redirection::Fd { fd: 3, mode: FileMode::Write, target: Word::from("fd.log") });
+        assert_eq!(reds[2], Redirection::Fd { fd: 2, mode: FileMode::Append, target: Word::from("err.log") });
+        assert_eq!(reds[3], Redirection::Dup { fd: 1, target: 2 });
+        assert_eq!(reds[4], Redirection::Close(4));
+        assert_eq!(reds[5], Redirection::Both { target: Word::from("both.log"), append: false });
+        assert!(matches!(reds[6], Redirection::HereString(_)));
+
+        // `2>&1` is just another dup, so a longer target isn't cut short
+        let (_, pipeline) = parse_pipeline("cmd 2>&1 2>&10").unwrap();
+        assert_eq!(pipeline.commands[0].args, vec!["cmd"]);
+        assert_eq!(pipeline.commands[0].redirections, vec![
+            Redirection::Dup { fd: 2, target: 1 },
+            Redirection::Dup { fd: 2, target: 10 },
+        ]);
+
+        // A number that isn't directly followed by an operator is an argument
+        let (_, pipeline) = parse_pipeline("head -n 2 > out").unwrap();
+        assert_eq!(pipeline.commands[0].args, vec!["head", "-n", "2"]);
+    }
+
+    #[test]
+    fn test_parse_heredocs() {
+        let input = "cat <<EOF | tr a b; cat <<-'END'\nhello $USER\nEOF\n\tliteral $USER\n\tEND\necho done";
+        let list = parse_input(input).unwrap();
+        let CommandList::Sequence(items) = &list else { panic!("expected a sequence, got {:?}", list) };
+        assert_eq!(items.len(), 3);
+
+        let heredoc = |item: &CommandList| match item {
+            CommandList::Pipeline(p) => match &p.commands[0].redirections[0] {
+                Redirection::HereDoc(doc) => doc.clone(),
+                other => panic!("expected a heredoc, got {:?}", other),
+            },
+            other => panic!("expected a pipeline, got {:?}", other),
+        };
+        let first = heredoc(&items[0]);
+        assert_eq!(first.delimiter, "EOF");
+        assert_eq!(first.body.parts[0], WordPart::Literal("hello ".to_string()));
+        assert!(matches!(first.body.parts[1], WordPart::Param(_)));
+        assert_eq!(first.body.parts[2], WordPart::Literal("\n".to_string()));
+
+        let second = heredoc(&items[1]);
+        assert!(second.quoted && second.strip_tabs);
+        assert_eq!(second.body.parts, vec![WordPart::SingleQuoted("literal $USER\n".to_string())]);
+
+        assert!(needs_more_input("cat <<EOF\nstill typing"));
+        assert!(!needs_more_input(input));
+    }
+
+    fn compound(list: &CommandList) -> &Compound {
+        match list {
+            CommandList::Pipeline(p) => p.commands[0].compound.as_deref().expect("expected a compound command"),
+            other => panic!("expected a pipeline, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_compound_commands() {
+        let list = parse_input("if test -f a; then\n  echo a\nelif false; then echo b\nelse\n  echo c; fi > out").unwrap();
+        let Compound::If { branches, else_branch } = compound(&list) else { panic!("expected if") };
+        assert_eq!(branches.len(), 2);
+        assert_eq!(pipeline_args(&branches[0].0), vec!["test", "-f", "a"]);
+        assert_eq!(pipeline_args(else_branch.as_ref().unwrap()), vec!["echo", "c"]);
+        let CommandList::Pipeline(p) = &list else { unreachable!() };
+        assert_eq!(p.commands[0].redirections, vec![Redirection::Stdout(Word::from("out"))]);
+
+        let list = parse_input("for f in *.rs src/*.rs\ndo echo $f; done | sort").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        assert_eq!(p.commands.len(), 2);
+        let Compound::For { var, words, .. } = p.commands[0].compound.as_deref().unwrap() else { panic!("expected for") };
+        assert_eq!(var, "f");
+        assert_eq!(words, &vec!["*.rs", "src/*.rs"]);
+
+        let list = parse_input("case $1 in\n  (*.rs|*.toml) echo rust;;\n  *) echo other\nesac").unwrap();
+        let Compound::Case { arms, .. } = compound(&list) else { panic!("expected case") };
+        assert_eq!(arms[0].patterns, vec!["*.rs", "*.toml"]);
+        assert_eq!(pipeline_args(&arms[1].body), vec!["echo", "other"]);
+        assert_eq!(list.to_string(), "case ${1} in *.rs|*.toml) echo rust;; *) echo other;; esac");
+
+        let list = parse_input("until test -f done; do sleep 1; done").unwrap();
+        assert!(matches!(compound(&list), Compound::While { until: true, .. }));
+        assert_eq!(list.to_string(), "until test -f done; do sleep 1; done");
+
+        // Reserved words are ordinary arguments outside command position
+        let (_, pipeline) = parse_pipeline("echo if then fi").unwrap();
+        assert_eq!(pipeline.commands[0].args, vec!["echo", "if", "then", "fi"]);
+
+        assert!(needs_more_input("if true; then\n  echo yes"));
+        assert!(needs_more_input("for x in a b"));
+        assert!(needs_more_input("case $x in\n  a) echo a;;"));
+        assert!(needs_more_input("while true; do\n  if false; then echo; fi"));
+        assert!(!needs_more_input("while true; do echo; done"));
+    }
+
+    #[test]
+    fn test_parse_functions() {
+        let list = parse_input("greet() {\n  echo hi $1\n}").unwrap();
+        let Compound::Function { name, body } = compound(&list) else { panic!("expected a function") };
+        assert_eq!(name, "greet");
+        assert_eq!(pipeline_args(body), vec!["echo", "hi", "${1}"]);
+        assert_eq!(list.to_string(), "greet() { echo hi ${1}; }");
+
+        let list = parse_input("fn count { for x in \"$@\"; do echo $x; done; }").unwrap();
+        assert!(matches!(compound(&list), Compound::Function { name, .. } if name == "count"));
+        // What gets saved to functions.json has to parse back to the same thing
+        assert_eq!(parse_input(&list.to_string()).unwrap(), list);
+
+        // `}` only closes the body as a command of its own
+        assert!(needs_more_input("fn f { echo }"));
+        assert!(needs_more_input("f() {"));
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let list = parse_input("#!/usr/bin/env chev\n# don't <<EOF\necho a#b '#c' # trailing\n").unwrap();
+        assert_eq!(pipeline_args(&list), vec!["echo", "a#b", "'#c'"]);
+        let CommandList::Sequence(items) = parse_input("echo one # first\n\n# nothing here\necho two").unwrap() else {
+            panic!("expected a sequence")
+        };
+        assert_eq!(items.len(), 2);
+        assert!(!needs_more_input("echo hi # if then do"));
+    }
+
+    #[test]
+    fn test_parse_assignments() {
+        let list = parse_input("FOO=bar EMPTY= DIR=\"$HOME/x y\" cargo run X=not-an-assignment").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        let names: Vec<_> = p.commands[0].assignments.iter().map(|a| a.to_string()).collect();
+        assert_eq!(names, vec!["FOO=bar", "EMPTY=", "DIR=\"${HOME}/x y\""]);
+        assert_eq!(pipeline_args(&list), vec!["cargo", "run", "X=not-an-assignment"]);
+
+        let list = parse_input("A=1 B=$A").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        assert!(p.commands[0].args.is_empty());
+        assert_eq!(p.commands[0].assignments.len(), 2);
+        // Quoted, it's just a word
+        assert_eq!(pipeline_args(&parse_input("'A=1' echo").unwrap()), vec!["'A=1'", "echo"]);
+    }
+
+    #[test]
+    fn test_parse_subshells_and_groups() {
+        let list = parse_input("(cd sub && make) | tee log").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        assert!(matches!(p.commands[0].compound.as_deref(), Some(Compound::Subshell(CommandList::And(..)))));
+        assert_eq!(list.to_string(), "( cd sub && make ) | tee log");
+
+        let list = parse_input("{ echo header; cat data; } > out").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        assert!(matches!(p.commands[0].compound.as_deref(), Some(Compound::Group(CommandList::Sequence(_)))));
+        assert_eq!(p.commands[0].redirections.len(), 1);
+        assert_eq!(parse_input(&list.to_string()).unwrap(), list);
+
+        // Brace expansion is still a word
+        assert_eq!(pipeline_args(&parse_input("echo {a,b}").unwrap()), vec!["echo", "{a,b}"]);
+        assert!(needs_more_input("(echo a"));
+        assert!(needs_more_input("{ echo a"));
+    }
+
+    #[test]
+    fn test_parse_arithmetic() {
+        let list = parse_input("echo $(( (i + 1) * $n )) \"$((i<<2))\"").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        assert!(matches!(&p.commands[0].args[1].parts[..], [WordPart::Arithmetic(_)]));
+        assert_eq!(pipeline_args(&list), vec!["echo", "$(( (i + 1) * ${n} ))", "\"$((i<<2))\""]);
+
+        // A command substitution that starts with a subshell isn't arithmetic
+        let list = parse_input("echo $( (echo a) )").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        assert!(matches!(&p.commands[0].args[1].parts[..], [WordPart::CommandSubst(_)]));
+
+        // `<<` inside is a shift, not a here-document
+        assert!(!needs_more_input("echo $((1 << 4))"));
+        assert!(needs_more_input("echo $((1 +"));
+    }
+
+    #[test]
+    fn test_parse_process_substitution() {
+        let list = parse_input("diff <(sort a) <(sort b) > out").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        let cmd = &p.commands[0];
+        assert_eq!(cmd.args.len(), 3);
+        assert!(matches!(&cmd.args[1].parts[..], [WordPart::ProcessSubst { output: false, .. }]));
+        assert_eq!(cmd.redirections, vec![Redirection::Stdout(Word::from("out"))]);
+        assert_eq!(pipeline_args(&list), vec!["diff", "<(sort a)", "<(sort b)"]);
+
+        // As a redirection target, and the output direction
+        let list = parse_input("cat < <(ls) | tee >(wc -l) x=>(cat)").unwrap();
+        let CommandList::Pipeline(p) = &list else { panic!("expected a pipeline") };
+        let Redirection::Stdin(target) = &p.commands[0].redirections[0] else { panic!("expected a redirection") };
+        assert!(matches!(&target.parts[..], [WordPart::ProcessSubst { output: false, .. }]));
+        assert!(matches!(&p.commands[1].args[1].parts[..], [WordPart::ProcessSubst { output: true, .. }]));
+        assert!(matches!(&p.commands[1].args[2].parts[..], [WordPart::Literal(_), WordPart::ProcessSubst { output: true, .. }]));
+
+        assert!(needs_more_input("diff <(sort a"));
+    }
+
+    fn first_error(input: &str) -> ParseError {
+        parse_input(input).unwrap_err().errors.remove(0)
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = first_error("echo a | | b");
+        assert_eq!(err.message, "unexpected `|`");
+        assert_eq!(err.span, 9..10);
+        assert_eq!(err.to_string(), "syntax error: unexpected `|`\n  echo a | | b\n           ^");
+
+        // Leftover input is an error rather than being dropped
+        let err = first_error("echo ok; done");
+        assert_eq!((err.message.as_str(), err.span), ("unexpected `done`", 9..13));
+        assert_eq!(first_error("echo a )").message, "unexpected `)`");
+
+        // A descriptor number too big to be one, though it's fine as an argument
+        let err = first_error("echo 99999999999>f");
+        assert_eq!((err.message.as_str(), err.span), ("bad file descriptor", 5..16));
+        assert_eq!(first_error("echo a >&99999999999").span, 9..20);
+        assert!(parse_input("echo 99999999999 > f").is_ok());
+
+        let err = first_error("echo \"it's here");
+        assert_eq!((err.message.as_str(), err.span), ("unterminated quote", 5..6));
+        assert_eq!(first_error("echo $(ls").message, "unexpected end of input");
+
+        // Offsets count the here-document bodies that were taken out before parsing
+        let err = first_error("cat <<EOF\nbody\nEOF\n\techo fi )");
+        assert_eq!(err.span, 28..29);
+        assert_eq!(err.position(), (4, 10));
+        assert!(err.to_string().ends_with("on line 4\n  \techo fi )\n  \t        ^"));
+
+        // Parsing carries on past the next separator, so every line's mistake is reported
+        let errors = parse_input("echo a | | b\necho ok\nfi\necho 'a; b' ) ; echo c >").unwrap_err().errors;
+        let found: Vec<_> = errors.iter().map(|e| (e.message.as_str(), e.position())).collect();
+        assert_eq!(found, vec![
+            ("unexpected `|`", (1, 10)),
+            ("unexpected `fi`", (3, 1)),
+            ("unexpected `)`", (4, 13)),
+            ("unexpected `>`", (4, 24)),
+        ]);
+        assert_eq!(parse_input("(echo a | | b; echo c)").unwrap_err().errors.len(), 2);
+
+        assert!(needs_more_input("ls |"));
+        assert!(needs_more_input("make &&"));
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("cat <<EOF 2>&1 | grep \"a b\"c >>log\nbody\nEOF\ndiff <(sort x) $(ls) && echo hi; # done"),
+            vec!["cat", "<<", "EOF", "2>&", "1", "|", "grep", "\"a b\"c", ">>", "log", "\n", "diff", "<(sort x)", "$(ls)", "&&", "echo", "hi", ";"]
         );
     }
 }
@@ -0,0 +1,283 @@
+use crate::engine::builtins::BuiltinRegistry;
+use crate::engine::env::EnvManager;
+use crate::engine::expand::expand_word_to_string;
+use crate::engine::jobs::JobManager;
+use crate::engine::macros::MacroManager;
+use crate::engine::parser::{FileMode, Redirection};
+use anyhow::{Result, anyhow};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The descriptors a command will start with, built up by applying its redirections in order.
+/// Fds that aren't in the table are left as the pipeline set them up.
+#[derive(Default, Clone)]
+pub struct FdTable {
+    // None means the fd is closed
+    fds: BTreeMap<i32, Option<Arc<OwnedFd>>>,
+}
+
+impl FdTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A table with whichever of stdin, stdout and stderr are given already set.
+    pub fn from_stdio(stdin: Option<Arc<OwnedFd>>, stdout: Option<Arc<OwnedFd>>, stderr: Option<Arc<OwnedFd>>) -> Self {
+        let mut table = Self::new();
+        for (fd, file) in [(0, stdin), (1, stdout), (2, stderr)] {
+            if let Some(file) = file {
+                table.set_fd(fd, file);
+            }
+        }
+        table
+    }
+
+    pub async fn apply(&mut self, red: &Redirection, jobs: &Arc<Mutex<JobManager>>, env_mutex: &Arc<Mutex<EnvManager>>, macros: &Arc<Mutex<MacroManager>>, builtins: &Arc<BuiltinRegistry>) -> Result<()> {
+        match red {
+            Redirection::Stdout(w) => {
+                let path = expand_word_to_string(w, jobs, env_mutex, macros, builtins).await?;
+                let file = File::create(path).map_err(|e| anyhow!("Failed to create output file: {}", e))?;
+                self.set(1, file.into());
+            }
+            Redirection::Stderr(w) => {
+                let path = expand_word_to_string(w, jobs, env_mutex, macros, builtins).await?;
+                let file = File::create(path).map_err(|e| anyhow!("Failed to create error file: {}", e))?;
+                self.set(2, file.into());
+            }
+            Redirection::Append(w) => {
+                let path = expand_word_to_string(w, jobs, env_mutex, macros, builtins).await?;
+                self.set(1, open(&path, FileMode::Append)?);
+            }
+            Redirection::Stdin(w) => {
+                let path = expand_word_to_string(w, jobs, env_mutex, macros, builtins).await?;
+                self.set(0, open(&path, FileMode::Read)?);
+            }
+            Redirection::Fd { fd, mode, target } => {
+                let path = expand_word_to_string(target, jobs, env_mutex, macros, builtins).await?;
+                self.set(*fd, open(&path, *mode)?);
+            }
+            Redirection::Both { target, append } => {
+                let path = expand_word_to_string(target, jobs, env_mutex, macros, builtins).await?;
+                let mode = if *append { FileMode::Append } else { FileMode::Write };
+                let file = Arc::new(open(&path, mode)?);
+                self.fds.insert(1, Some(Arc::clone(&file)));
+                self.fds.insert(2, Some(file));
+            }
+            Redirection::HereDoc(doc) => {
+                let body = expand_word_to_string(&doc.body, jobs, env_mutex, macros, builtins).await?;
+                self.set(0, feed(body)?);
+            }
+            Redirection::HereString(w) => {
+                let text = expand_word_to_string(w, jobs, env_mutex, macros, builtins).await?;
+                self.set(0, feed(format!("{}\n", text))?);
+            }
+            Redirection::Dup { fd, target } => self.dup(*fd, *target)?,
+            Redirection::Close(fd) => self.close(*fd),
+        }
+        Ok(())
+    }
+
+    /// Points `fd` at an already open descriptor, e.g. one end of a pipeline pipe.
+    pub fn set_fd(&mut self, fd: i32, file: Arc<OwnedFd>) {
+        self.fds.insert(fd, Some(file));
+    }
+
+    /// Marks `fd` to be closed in the child.
+    pub fn close(&mut self, fd: i32) {
+        self.fds.insert(fd, None);
+    }
+
+    /// What `fd` has been pointed at, if it's open and in the table.
+    pub fn get(&self, fd: i32) -> Option<Arc<OwnedFd>> {
+        self.fds.get(&fd).cloned().flatten()
+    }
+
+    fn set(&mut self, fd: i32, file: OwnedFd) {
+        self.fds.insert(fd, Some(Arc::new(file)));
+    }
+
+    /// `fd>&target`: fd becomes a copy of what target refers to right now,
+    /// so `> f 2>&1` and `2>&1 > f` differ like they do in POSIX sh.
+    fn dup(&mut self, fd: i32, target: i32) -> Result<()> {
+        let source = self.resolve(target)?;
+        self.fds.insert(fd, Some(source));
+        Ok(())
+    }
+
+    /// What `fd` currently refers to, falling back to the shell's own stdin, stdout or stderr.
+    fn resolve(&self, fd: i32) -> Result<Arc<OwnedFd>> {
+        match self.fds.get(&fd) {
+            Some(Some(file)) => Ok(Arc::clone(file)),
+            Some(None) => Err(anyhow!("{}: Bad file descriptor", fd)),
+            // Whatever else the shell has open (the runtime's own pipes and sockets) isn't the script's
+            None if fd > 2 => Err(anyhow!("{}: Bad file descriptor", fd)),
+            None => {
+                let own = unsafe { BorrowedFd::borrow_raw(fd) };
+                let copy = own.try_clone_to_owned().map_err(|_| anyhow!("{}: Bad file descriptor", fd))?;
+                Ok(Arc::new(copy))
+            }
+        }
+    }
+
+    /// The lowest descriptor above every one in the table, for handing a child something more.
+    pub fn next_free(&self) -> i32 {
+        self.fds.keys().copied().max().unwrap_or(2).max(2) + 1
+    }
+
+    /// Wires the table into the command: stdio through `Stdio`, anything else with `dup2` in the child.
+    pub fn configure(&self, cmd: &mut Command) -> Result<()> {
+        let mut moves = Vec::new();
+        let mut closes = Vec::new();
+        let highest = self.fds.keys().copied().max().unwrap_or(2).max(2);
+
+        for (&fd, entry) in &self.fds {
+            match (fd, entry) {
+                (0, Some(file)) => { cmd.stdin(Stdio::from(file.try_clone()?)); }
+                (1, Some(file)) => { cmd.stdout(Stdio::from(file.try_clone()?)); }
+                (2, Some(file)) => { cmd.stderr(Stdio::from(file.try_clone()?)); }
+                (fd, Some(file)) => moves.push((park(fd, file, highest)?, fd)),
+                (fd, None) => {
+                    match fd {
+                        0 => { cmd.stdin(Stdio::inherit()); }
+                        1 => { cmd.stdout(Stdio::inherit()); }
+                        2 => { cmd.stderr(Stdio::inherit()); }
+                        _ => {}
+                    }
+                    closes.push(fd);
+                }
+            }
+        }
+
+        if !moves.is_empty() || !closes.is_empty() {
+            unsafe {
+                cmd.pre_exec(move || {
+                    for (source, fd) in &moves {
+                        if libc::dup2(source.as_raw_fd(), *fd) < 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    for fd in &closes {
+                        libc::close(*fd);
+                    }
+                    Ok(())
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies `file` above every target fd, so a `dup2` onto one target can't clobber another's source.
+fn park(fd: i32, file: &OwnedFd, highest: i32) -> Result<OwnedFd> {
+    let above = highest.checked_add(1).ok_or_else(|| anyhow!("{}: bad file descriptor", fd))?;
+    let raw = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_DUPFD_CLOEXEC, above) };
+    if raw < 0 {
+        return Err(anyhow!("{}: {}", fd, std::io::Error::last_os_error()));
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(raw) })
+}
+
+fn open(path: &str, mode: FileMode) -> Result<OwnedFd> {
+    let file = match mode {
+        FileMode::Read => File::open(path).map_err(|e| anyhow!("Failed to open input file: {}", e))?,
+        FileMode::Write => File::create(path).map_err(|e| anyhow!("Failed to create output file: {}", e))?,
+        FileMode::Append => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path).map_err(|e| anyhow!("Failed to open append file: {}", e))?,
+    };
+    Ok(file.into())
+}
+
+/// A descriptor to read `text` from, for heredocs and herestrings.
+pub(crate) fn feed(text: String) -> Result<OwnedFd> {
+    // A body that fits in the pipe buffer is written straight in; a bigger one would block
+    // until the command reads it, so like bash it goes to an unlinked temporary file instead
+    if text.len() <= libc::PIPE_BUF {
+        let (reader, mut writer) = std::io::pipe()?;
+        writer.write_all(text.as_bytes())?;
+        return Ok(reader.into());
+    }
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!("chev-heredoc-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)));
+    let mut file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)
+        .map_err(|e| anyhow!("Failed to create heredoc file: {}", e))?;
+    let _ = std::fs::remove_file(&path);
+    file.write_all(text.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::test_support::Shell;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_input_redirections() {
+        let shell = Shell::new();
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("in.txt");
+        std::fs::write(&input, "from file\n").unwrap();
+        shell.set("CHEV_REDIR_NAME", "chev");
+
+        let script = format!(
+            "cat < {}; cat <<EOF\nhello $CHEV_REDIR_NAME\nEOF\ncat <<< \"$CHEV_REDIR_NAME here\"; sh -c 'echo three >&3' 3>&1",
+            input.display(),
+        );
+        assert_eq!(shell.output(&script).await, "from file\nhello chev\nchev here\nthree");
+
+        let out = dir.path().join("out.txt");
+        std::fs::write(&out, "one\n").unwrap();
+        shell.run(&format!("sh -c 'echo two >&3' 3>> {}", out.display())).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "one\ntwo\n");
+
+        // A body bigger than the pipe buffer arrives whole
+        shell.set("CHEV_REDIR_BIG", &"x".repeat(100_000));
+        assert_eq!(shell.output("awk '{ print length }' <<< \"$CHEV_REDIR_BIG\"").await, "100000");
+    }
+
+    #[tokio::test]
+    async fn test_fd_duplication_order() {
+        let shell = Shell::new();
+        let dir = tempdir().unwrap();
+        let both = dir.path().join("both.txt");
+        let noisy = "sh -c 'echo out; echo err >&2'";
+
+        // `> f 2>&1` sends both streams to the file
+        shell.run(&format!("{} > {} 2>&1", noisy, both.display())).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&both).unwrap(), "out\nerr\n");
+
+        // `2>&1` before the pipe carries stderr into it
+        assert_eq!(shell.output(&format!("{} 2>&1 | sort", noisy)).await, "err\nout");
+
+        // `2>&1 > f` leaves stderr where stdout was, so only stdout lands in the file
+        assert_eq!(shell.output(&format!("{} 2>&1 > {}", noisy, both.display())).await, "err");
+        assert_eq!(std::fs::read_to_string(&both).unwrap(), "out\n");
+
+        // Fds past stderr that the script never opened can't be borrowed from the shell,
+        // whatever the runtime happens to have open there
+        let open_in_shell = (3..64).find(|&fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } != -1).unwrap();
+        let err = shell.run(&format!("true >&{}", open_in_shell)).await.unwrap_err();
+        assert_eq!(err.to_string(), format!("{}: Bad file descriptor", open_in_shell));
+    }
+
+    #[tokio::test]
+    async fn test_redirection_only_command() {
+        let shell = Shell::new();
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("log.txt");
+        std::fs::write(&file, "old\n").unwrap();
+        shell.run(&format!("> {}", file.display())).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "");
+
+        assert!(shell.run("< /nonexistent/file").await.is_err());
+        assert_eq!(shell.status(), 1);
+    }
+}
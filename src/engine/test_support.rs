@@ -0,0 +1,61 @@
+use crate::engine::builtins::BuiltinRegistry;
+use crate::engine::env::EnvManager;
+use crate::engine::executor::{capture_output, execute_command};
+use crate::engine::expand::expand_words;
+use crate::engine::jobs::{reap_on_sigchld, JobManager};
+use crate::engine::macros::MacroManager;
+use crate::engine::parser::{parse_input, parse_pipeline};
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use tempfile::{tempdir, TempDir};
+
+/// A shell for tests, with the managers the executor takes.
+pub struct Shell {
+    pub jobs: Arc<Mutex<JobManager>>,
+    pub env: Arc<Mutex<EnvManager>>,
+    pub macros: Arc<Mutex<MacroManager>>,
+    pub builtins: Arc<BuiltinRegistry>,
+    // Keeps macros and functions out of the real ~/.chev
+    _config: TempDir,
+}
+
+impl Shell {
+    /// Needs a runtime: like the real shell, it reaps background jobs on SIGCHLD.
+    pub fn new() -> Self {
+        let config = tempdir().unwrap();
+        let jobs = Arc::new(Mutex::new(JobManager::new()));
+        reap_on_sigchld(&jobs).unwrap();
+        Self {
+            jobs,
+            env: Arc::new(Mutex::new(EnvManager::new())),
+            macros: Arc::new(Mutex::new(MacroManager::new_at_path(config.path().to_path_buf()))),
+            builtins: Arc::new(BuiltinRegistry::new()),
+            _config: config,
+        }
+    }
+
+    /// Runs `input` as if typed at the prompt.
+    pub async fn run(&self, input: &str) -> Result<()> {
+        execute_command(input, &self.jobs, &self.env, &self.macros, &self.builtins).await
+    }
+
+    /// What `input` prints, as `$(...)` would see it, so without trailing newlines.
+    pub async fn output(&self, input: &str) -> String {
+        let list = parse_input(input).unwrap();
+        capture_output(&list, &self.jobs, &self.env, &self.macros, &self.builtins).await.unwrap()
+    }
+
+    /// The words the first command of `input` expands to.
+    pub async fn expand(&self, input: &str) -> Vec<String> {
+        let (_, pipeline) = parse_pipeline(input).unwrap();
+        expand_words(&pipeline.commands[0].args, &self.jobs, &self.env, &self.macros, &self.builtins).await.unwrap()
+    }
+
+    pub fn set(&self, key: &str, value: &str) {
+        self.env.lock().unwrap().set_var(key.to_string(), value.to_string());
+    }
+
+    pub fn status(&self) -> i32 {
+        self.env.lock().unwrap().last_status()
+    }
+}
@@ -4,18 +4,42 @@ use rustyline::{Cmd, KeyEvent, KeyCode, Modifiers, Movement, Word, At};
 use clap::Parser;
 
 use std::sync::{Arc, Mutex};
-use chev_shell::engine::jobs::JobManager;
+use chev_shell::engine::jobs::{reap_on_sigchld, JobManager};
 use chev_shell::engine::env::EnvManager;
 use chev_shell::engine::macros::MacroManager;
+use chev_shell::engine::builtins::BuiltinRegistry;
 use chev_shell::{engine, ui};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "🐕 Chev Shell - An AI-native shell built in Rust")]
+// A first word that names a subcommand is the subcommand, so a script called `parse` or `ai`
+// is run as `./parse`; once a script is given, the words after it are all its arguments
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
     /// Command to execute directly (optional)
     #[arg(short, long)]
     command: Option<String>,
 
+    /// Don't source /etc/chev/chevrc and ~/.chev/chevrc at startup
+    #[arg(long)]
+    norc: bool,
+
+    /// Show how the -c command or script is parsed, expanded and resolved instead of running it
+    #[arg(long)]
+    dump_ast: bool,
+
+    /// Script file to run (with -c, the name to use for $0). A script named like a
+    /// subcommand needs a path, e.g. `./parse`
+    script: Option<String>,
+
+    /// Arguments for the script, available as $1, $2, ...
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    script_args: Vec<String>,
+
+    /// Run as a subshell, with the shell code and state handed over on this descriptor
+    #[arg(long, hide = true)]
+    subshell: Option<i32>,
+
     #[command(subcommand)]
     subcommand: Option<Commands>,
 }
@@ -27,6 +51,11 @@ enum Commands {
         #[command(subcommand)]
         action: AiAction,
     },
+    /// Show how a command line is parsed, expanded and resolved, without running it
+    Parse {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
     /// Internal tools
     Internal {
         #[command(subcommand)]
@@ -67,32 +96,14 @@ async fn main() -> anyhow::Result<()> {
     let jobs = Arc::new(Mutex::new(JobManager::new()));
     let env_manager = Arc::new(Mutex::new(EnvManager::new()));
     let macro_manager = Arc::new(Mutex::new(MacroManager::new()));
+    let builtins = Arc::new(BuiltinRegistry::new());
 
-    // Background task to reap zombie processes
-    let jobs_for_reaper = Arc::clone(&jobs);
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
-        loop {
-            interval.tick().await;
-            let mut jobs = jobs_for_reaper.lock().unwrap();
-            let mut to_remove = Vec::new();
-
-            for job in jobs.get_jobs() {
-                // Non-blocking check for process status
-                let pid = job.pgid;
-                match nix::sys::wait::waitpid(pid, Some(nix::sys::wait::WaitPidFlag::WNOHANG)) {
-                    Ok(nix::sys::wait::WaitStatus::Exited(_, _)) | Ok(nix::sys::wait::WaitStatus::Signaled(_, _, _)) => {
-                        to_remove.push(pid);
-                    }
-                    _ => {}
-                }
-            }
+    reap_on_sigchld(&jobs)?;
 
-            for pid in to_remove {
-                jobs.remove_job(pid);
-            }
-        }
-    });
+    if let Some(fd) = args.subshell {
+        let code = engine::executor::run_subshell(fd, &jobs, &env_manager, &macro_manager, &builtins).await;
+        std::process::exit(code);
+    }
 
     if let Some(Commands::Ai { action }) = args.subcommand {
         match action {
@@ -131,15 +142,66 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    let dump_input = match (&args.subcommand, args.dump_ast) {
+        (Some(Commands::Parse { command }), _) => Some(command.join(" ")),
+        (_, true) => match (&args.command, &args.script) {
+            (Some(cmd), _) => Some(cmd.clone()),
+            (None, Some(path)) => Some(std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?),
+            (None, None) => Some(std::io::read_to_string(std::io::stdin())?),
+        },
+        _ => None,
+    };
+    if let Some(input) = dump_input {
+        match engine::dump::dump(&input, &jobs, &env_manager, &macro_manager, &builtins).await {
+            Ok(out) => print!("{}", out),
+            Err(e) => {
+                eprintln!("Chev Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = &args.script {
+        // Like `sh -c CMD NAME ARGS...` or `sh SCRIPT ARGS...`
+        let mut env = env_manager.lock().unwrap();
+        env.set_arg0(name.clone());
+        env.set_positional(args.script_args.clone());
+    }
+
     if let Some(cmd) = args.command {
         // Execute a single command and exit
-        if let Err(e) = engine::executor::execute_command(&cmd, &jobs, &env_manager, &macro_manager).await {
-            eprintln!("Chev Error: {}", e);
-            std::process::exit(1);
+        if let Err(e) = engine::executor::execute_command(&cmd, &jobs, &env_manager, &macro_manager, &builtins).await {
+            if e.downcast_ref::<engine::executor::ExitStatusError>().is_none() {
+                eprintln!("Chev Error: {}", e);
+            }
+        }
+        engine::executor::run_exit_trap(&jobs, &env_manager, &macro_manager, &builtins).await;
+        // Exit with the command's own status, like `sh -c`
+        let code = env_manager.lock().unwrap().last_status();
+        if code != 0 {
+            std::process::exit(code);
         }
         return Ok(());
     }
 
+    if let Some(path) = args.script {
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Chev Error: {}: {}", path, e);
+                std::process::exit(127);
+            }
+        };
+        if let Err(e) = engine::executor::execute_script(&source, &jobs, &env_manager, &macro_manager, &builtins).await {
+            if e.downcast_ref::<engine::executor::ExitStatusError>().is_none() {
+                eprintln!("Chev Error: {}", e);
+            }
+        }
+        engine::executor::run_exit_trap(&jobs, &env_manager, &macro_manager, &builtins).await;
+        std::process::exit(env_manager.lock().unwrap().last_status());
+    }
+
     let checker = engine::executor::AiChecker::new();
     let model_name = std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| "qwen2.5-coder:7b".to_string());
     let ollama_running = checker.is_ollama_running().await;
@@ -200,7 +262,10 @@ async fn main() -> anyhow::Result<()> {
         "tldr", "heh", "lemmeknow", "kibi", "btm", "procs", "hyperfine", 
         "just", "hwatch", "doggo", "gping", "xh", "fend", "ouch"
     ];
-    let installed_count = tools_to_check.iter().filter(|t| which::which(t).is_ok()).count();
+    // Tools installed by `ai setup` live in ~/.chev/bin, which is only on the shell's own $PATH
+    let search_path = env_manager.lock().unwrap().get_var("PATH").cloned();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let installed_count = tools_to_check.iter().filter(|t| which::which_in(t, search_path.as_ref(), &cwd).is_ok()).count();
     let total_count = tools_to_check.len();
     
     let blue = "\x1b[38;2;67;147;255m";
@@ -279,7 +344,19 @@ async fn main() -> anyhow::Result<()> {
     });
 
     let mut rl = rustyline::Editor::<ui::suggestions::ShellHelper, rustyline::history::FileHistory>::new()?;
-    rl.set_helper(Some(ui::suggestions::ShellHelper::new(Arc::clone(&macro_manager), Arc::clone(&ghost_state), semantic_active)));
+    rl.set_helper(Some(ui::suggestions::ShellHelper::new(Arc::clone(&macro_manager), Arc::clone(&builtins), Arc::clone(&ghost_state), semantic_active)));
+
+    // Ctrl+C and Ctrl+\ are meant for the foreground job. The shell only runs whatever
+    // traps are set for them, and like bash it won't be ended by a stray TERM either.
+    // The editor claimed SIGINT for itself; at the prompt it sees Ctrl+C as a key anyway.
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+    env_manager.lock().unwrap().set_interactive(true);
+    for signal in [libc::SIGINT, libc::SIGQUIT, libc::SIGTERM] {
+        engine::executor::catch_signal(signal, &env_manager)?;
+    }
 
     // Key Bindings: Accept hint with Tab or Right Arrow
     rl.bind_sequence(KeyEvent(KeyCode::Tab, Modifiers::NONE), Cmd::Complete);
@@ -304,7 +381,28 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    // System-wide settings first, so the user's own file can override them
+    if !args.norc {
+        for rc in [std::path::PathBuf::from("/etc/chev/chevrc"), chev_dir.join("chevrc")] {
+            if !rc.exists() {
+                continue;
+            }
+            if let Err(e) = engine::executor::source_file(&rc, &jobs, &env_manager, &macro_manager, &builtins).await
+                && e.downcast_ref::<engine::executor::ExitStatusError>().is_none()
+            {
+                eprintln!("Chev Error: {}", e);
+            }
+        }
+    }
+
+    // Functions defined from here on are kept for the next session
+    macro_manager.lock().unwrap().set_persist_functions(true);
+
     loop {
+        engine::executor::run_traps(&jobs, &env_manager, &macro_manager, &builtins).await;
+        for notice in jobs.lock().unwrap().take_notices() {
+            println!("{}", notice);
+        }
         let prompt_parts = ui::prompt::get_prompt_parts();
         if let Some(helper) = rl.helper_mut() {
             helper.prompt_parts = prompt_parts.clone();
@@ -345,17 +443,14 @@ async fn main() -> anyhow::Result<()> {
                 }
 
                 // Execute via our engine
-                let result = engine::executor::execute_command(input, &jobs, &env_manager, &macro_manager).await;
-                
-                // Semantic: Block End (assuming exit code 0 on success, 1 on error for now)
-                if semantic_active {
-                    let exit_code = if result.is_ok() { 0 } else { 1 };
-                    println!("\x1b]133;D;{}\x07", exit_code);
-                }
+                // The executor emits OSC 133;D with the real exit status
+                let result = engine::executor::execute_command(input, &jobs, &env_manager, &macro_manager, &builtins).await;
 
                 if let Err(e) = result {
                     let err_str = e.to_string();
-                    if err_str.contains("os error 2") {
+                    if e.downcast_ref::<engine::executor::ExitStatusError>().is_some() {
+                        // The command already reported its own failure; `$?` has the code
+                    } else if err_str.contains("os error 2") {
                          eprintln!("\x1b[31m❌ Command not found: {}\x1b[0m", input.split_whitespace().next().unwrap_or(""));
                          eprintln!("\x1b[90m   Tip: Ask AI with 'ai ask \"{}\"'\x1b[0m", input);
                     } else {
@@ -365,23 +460,16 @@ async fn main() -> anyhow::Result<()> {
             }
             Err(ReadlineError::Interrupted) => {
                 if semantic_active { println!("\x1b]133;D;1\x07"); }
-                println!("SIGINT");
+                println!("^C");
+                // Runs an INT trap, if there is one, before the next prompt
+                let mut env = env_manager.lock().unwrap();
+                env.set_status(130, vec![130]);
+                env.queue_signal(libc::SIGINT);
                 continue;
             }
             Err(ReadlineError::Eof) => {
                 if semantic_active { println!("\x1b]133;D\x07"); }
                 println!("Exiting...");
-                // Terminate all active jobs
-                {
-                    let jobs = jobs.lock().unwrap();
-                    for job in jobs.get_jobs() {
-                        #[cfg(unix)]
-                        {
-                            use nix::sys::signal::{kill, Signal};
-                            let _ = kill(job.pgid, Signal::SIGTERM);
-                        }
-                    }
-                }
                 // Save suggestions
                 if let Some(helper) = rl.helper_mut() {
                     helper.trie.save(suggestions_path.to_str().unwrap());
@@ -396,6 +484,8 @@ async fn main() -> anyhow::Result<()> {
             }
         }
     }
+    // Background jobs keep running, unless an EXIT trap sees to them
+    engine::executor::run_exit_trap(&jobs, &env_manager, &macro_manager, &builtins).await;
     rl.save_history(&history_path)?;
     Ok(())
 }
\ No newline at end of file
@@ -1,11 +1,12 @@
 use rustyline::completion::Pair;
 use rustyline::Result;
 use std::path::Path;
+use crate::engine::builtins::{BuiltinRegistry, Completion};
 
 pub struct ChevCompleter;
 
 impl ChevCompleter {
-    pub fn complete(line: &str, pos: usize, macro_manager: &std::sync::Arc<std::sync::Mutex<crate::engine::macros::MacroManager>>, ghost_state: &std::sync::Arc<std::sync::Mutex<crate::ui::suggestions::GhostState>>) -> Result<(usize, Vec<Pair>)> {
+    pub fn complete(line: &str, pos: usize, macro_manager: &std::sync::Arc<std::sync::Mutex<crate::engine::macros::MacroManager>>, builtins: &BuiltinRegistry, ghost_state: &std::sync::Arc<std::sync::Mutex<crate::ui::suggestions::GhostState>>) -> Result<(usize, Vec<Pair>)> {
         let (before, _) = line.split_at(pos);
         let parts: Vec<&str> = before.split_whitespace().collect();
         
@@ -55,14 +56,34 @@ impl ChevCompleter {
             return Ok((pos, all_matches));
         }
 
-        // 2. Docker Completion
+        // 2. Builtins: their names for the command word, then what their first argument takes
+        let arg_index = if before.ends_with(char::is_whitespace) { parts.len() } else { parts.len() - 1 };
+        if arg_index == 0 {
+            let names = builtins.names().filter_map(|name| builtins.get(name).map(|b| (name, b.help())));
+            all_matches.extend(Self::complete_words(names, parts[0], pos).1);
+        } else if arg_index == 1 && let Some(builtin) = builtins.get(parts[0]) {
+            let target = parts.get(1).copied().unwrap_or("");
+            let (start, matches) = match builtin.completion() {
+                Completion::Words(words) => Self::complete_words(words.iter().copied(), target, pos),
+                Completion::Builtins => {
+                    let names = builtins.list().map(|b| (b.name(), b.help()));
+                    Self::complete_words(names, target, pos)
+                }
+                Completion::Nothing => (pos, Vec::new()),
+                Completion::Paths => Self::complete_path(before, pos)?,
+            };
+            all_matches.extend(matches);
+            return Ok((start, all_matches));
+        }
+
+        // 3. Docker Completion
         if parts[0] == "docker" {
             let (start, docker_matches) = Self::complete_docker(parts.get(1).copied(), before, pos);
             all_matches.extend(docker_matches);
             return Ok((start, all_matches));
         }
 
-        // 3. Fallback to File/Path Completion
+        // 4. Fallback to File/Path Completion
         let (start, path_matches) = Self::complete_path(before, pos)?;
         all_matches.extend(path_matches);
         Ok((start, all_matches))
@@ -80,20 +101,7 @@ impl ChevCompleter {
             ("diff", "Show changes between commits, commit and working tree"),
         ];
 
-        let mut matches = Vec::new();
-        let target = subcmd.unwrap_or("");
-        
-        for (name, desc) in commands {
-            if name.starts_with(target) {
-                matches.push(Pair {
-                    display: format!("{} - {}", name, desc),
-                    replacement: name.to_string(),
-                });
-            }
-        }
-
-        let start = pos - target.len();
-        (start, matches)
+        Self::complete_words(commands, subcmd.unwrap_or(""), pos)
     }
 
     fn complete_docker(subcmd: Option<&str>, _full: &str, pos: usize) -> (usize, Vec<Pair>) {
@@ -107,17 +115,18 @@ impl ChevCompleter {
             ("exec", "Run a command in a running container"),
         ];
 
-        let mut matches = Vec::new();
-        let target = subcmd.unwrap_or("");
-
-        for (name, desc) in commands {
-            if name.starts_with(target) {
-                matches.push(Pair {
-                    display: format!("{} - {}", name, desc),
-                    replacement: name.to_string(),
-                });
-            }
-        }
+        Self::complete_words(commands, subcmd.unwrap_or(""), pos)
+    }
+
+    /// The words that start with `target`, shown with their descriptions.
+    fn complete_words<'a>(words: impl IntoIterator<Item = (&'a str, &'a str)>, target: &str, pos: usize) -> (usize, Vec<Pair>) {
+        let matches = words.into_iter()
+            .filter(|(name, _)| name.starts_with(target))
+            .map(|(name, desc)| Pair {
+                display: format!("{} - {}", name, desc),
+                replacement: name.to_string(),
+            })
+            .collect();
 
         let start = pos - target.len();
         (start, matches)
@@ -5,4 +5,5 @@ pub mod completion;
 pub mod protocol;
 pub mod chat;
 pub mod browser;
+#[cfg(feature = "vibe")]
 pub mod vibe;
@@ -2,7 +2,7 @@ use rustyline::hint::{Hint, Hinter};
 use rustyline::Context;
 use rustyline::completion::Completer;
 use rustyline::highlight::Highlighter;
-use rustyline::validate::Validator;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::Helper;
 use std::borrow::Cow;
 
@@ -123,16 +123,18 @@ pub struct GhostState {
 pub struct ShellHelper {
     pub trie: CommandTrie,
     pub macro_manager: std::sync::Arc<std::sync::Mutex<crate::engine::macros::MacroManager>>,
+    pub builtins: std::sync::Arc<crate::engine::builtins::BuiltinRegistry>,
     pub ghost_state: std::sync::Arc<std::sync::Mutex<GhostState>>,
     pub prompt_parts: crate::ui::prompt::PromptParts,
     pub semantic_active: bool,
 }
 
 impl ShellHelper {
-    pub fn new(macro_manager: std::sync::Arc<std::sync::Mutex<crate::engine::macros::MacroManager>>, ghost_state: std::sync::Arc<std::sync::Mutex<GhostState>>, semantic_active: bool) -> Self {
+    pub fn new(macro_manager: std::sync::Arc<std::sync::Mutex<crate::engine::macros::MacroManager>>, builtins: std::sync::Arc<crate::engine::builtins::BuiltinRegistry>, ghost_state: std::sync::Arc<std::sync::Mutex<GhostState>>, semantic_active: bool) -> Self {
         Self { 
             trie: CommandTrie::new(),
             macro_manager,
+            builtins,
             ghost_state,
             prompt_parts: crate::ui::prompt::PromptParts::default(),
             semantic_active,
@@ -146,7 +148,7 @@ impl Completer for ShellHelper {
     type Candidate = rustyline::completion::Pair;
 
     fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
-        crate::ui::completion::ChevCompleter::complete(line, pos, &self.macro_manager, &self.ghost_state)
+        crate::ui::completion::ChevCompleter::complete(line, pos, &self.macro_manager, &self.builtins, &self.ghost_state)
     }
 }
 
@@ -213,4 +215,13 @@ impl Highlighter for ShellHelper {
     }
 }
 
-impl Validator for ShellHelper {}
+impl Validator for ShellHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        // Keep reading lines until every heredoc has seen its delimiter
+        if crate::engine::parser::needs_more_input(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use chev_shell::engine::builtins::BuiltinRegistry;
 use chev_shell::engine::executor::execute_command;
 use chev_shell::engine::jobs::JobManager;
 use chev_shell::engine::env::EnvManager;
@@ -9,9 +10,10 @@ async fn test_error_capture() {
     let jobs = Arc::new(Mutex::new(JobManager::new()));
     let env_manager = Arc::new(Mutex::new(EnvManager::new()));
     let macro_manager = Arc::new(Mutex::new(MacroManager::new()));
+    let builtins = Arc::new(BuiltinRegistry::new());
 
     // Run a command that definitely fails
-    let result = execute_command("nonexistentcommand", &jobs, &env_manager, &macro_manager).await;
+    let result = execute_command("nonexistentcommand", &jobs, &env_manager, &macro_manager, &builtins).await;
     
     // It should return an error
     assert!(result.is_err());
@@ -30,16 +32,17 @@ async fn test_success_clears_error() {
     let jobs = Arc::new(Mutex::new(JobManager::new()));
     let env_manager = Arc::new(Mutex::new(EnvManager::new()));
     let macro_manager = Arc::new(Mutex::new(MacroManager::new()));
+    let builtins = Arc::new(BuiltinRegistry::new());
 
     // 1. Fail first
-    let _ = execute_command("sh -c \"exit 1\"", &jobs, &env_manager, &macro_manager).await;
+    let _ = execute_command("sh -c \"exit 1\"", &jobs, &env_manager, &macro_manager, &builtins).await;
     {
         let macros = macro_manager.lock().unwrap();
         assert!(macros.last_error.is_some());
     }
 
     // 2. Succeed then
-    let _ = execute_command("sh -c \"exit 0\"", &jobs, &env_manager, &macro_manager).await;
+    let _ = execute_command("sh -c \"exit 0\"", &jobs, &env_manager, &macro_manager, &builtins).await;
     {
         let macros = macro_manager.lock().unwrap();
         assert!(macros.last_error.is_none());
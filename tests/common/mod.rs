@@ -0,0 +1,39 @@
+use std::process::Command;
+use tempfile::tempdir;
+
+/// What a `chev -c` child process did.
+pub struct Run {
+    pub status: i32,
+    // With the prompt's OSC sequences taken out
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs `script` with `chev --norc -c` in its own process, for tests that change
+/// process-wide state like the working directory or signal handlers.
+/// `$HOME` is a fresh directory so nothing touches the real `~/.chev`.
+pub fn chev(script: &str) -> Run {
+    let home = tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_chev"))
+        .args(["--norc", "-c", script])
+        .env("HOME", home.path())
+        .output()
+        .unwrap();
+    Run {
+        status: output.status.code().unwrap_or(-1),
+        stdout: strip_osc(&String::from_utf8_lossy(&output.stdout)),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
+
+/// Removes `ESC ] ... BEL` sequences.
+fn strip_osc(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b]") {
+        out.push_str(&rest[..start]);
+        rest = rest[start..].find('\x07').map_or("", |end| &rest[start + end + 1..]);
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,129 @@
+use tempfile::tempdir;
+
+mod common;
+use common::chev;
+
+#[test]
+fn test_source() {
+    let dir = tempdir().unwrap();
+    let lib = dir.path().join("lib.chev");
+    std::fs::write(&lib, "# helpers\nhello() { echo \"hello $1\"; }\nset GREETED yes\ncd $1\nreturn 2\necho unreachable\n").unwrap();
+
+    // `cd` changes the whole process's directory, so this runs in a shell of its own
+    let run = chev(&format!(
+        "source {lib} {dir}; echo \"status $?\"; hello there; echo \"$GREETED\"; pwd -P; . {lib} {dir}",
+        lib = lib.display(), dir = dir.path().display(),
+    ));
+    let dir = dir.path().canonicalize().unwrap();
+    assert_eq!(run.stdout, format!("status 2\nhello there\nyes\n{}\n", dir.display()));
+    assert_eq!(run.status, 2);
+
+    let run = chev("source /nonexistent/chevrc");
+    assert_ne!(run.status, 0);
+    assert!(run.stderr.contains("/nonexistent/chevrc"));
+}
+
+#[test]
+fn test_subshells_and_groups() {
+    let run = chev(
+        "CHEV_SCOPE=outer\n\
+         (CHEV_SCOPE=inner; echo \"sub $CHEV_SCOPE\") | tr a-z A-Z\n\
+         echo \"after $CHEV_SCOPE\"\n\
+         { echo header; echo \"$CHEV_SCOPE\"; } >&2\n\
+         { CHEV_SCOPE=group; }; echo \"after $CHEV_SCOPE\"\n\
+         (sh -c 'exit 6'); echo \"status $?\"",
+    );
+    assert_eq!(run.stdout, "SUB INNER\nafter outer\nafter group\nstatus 6\n");
+    // A group's redirection applies to everything in it
+    assert_eq!(run.stderr, "header\nouter\n");
+}
+
+#[test]
+fn test_process_substitution() {
+    let run = chev(
+        "paste <(echo one) <(echo two)\n\
+         sort < <(printf 'b\\na\\n')\n\
+         echo piped | tee >(tr a-z A-Z) > /dev/null\n\
+         chev_show() { head -n 1 \"$1\"; }; chev_show <(echo func)\n\
+         head -1 <(yes)",
+    );
+    assert_eq!(run.stdout, "one\ttwo\na\nb\nPIPED\nfunc\ny\n");
+}
+
+#[test]
+fn test_builtins_in_pipelines() {
+    let run = chev(
+        "export CHEV_PIPED=yes; export -p | awk /CHEV_PIPED/\n\
+         math 6 '*' 7\n\
+         echo ignored | math 2 + 3 | tr 5 6\n\
+         fg 99 2> /dev/null; echo \"fg $?\"",
+    );
+    assert_eq!(run.stdout, "export CHEV_PIPED=yes\n42\n6\nfg 1\n");
+}
+
+#[test]
+fn test_trap() {
+    // Signals go to the whole process, so this runs in a shell of its own
+    let run = chev(
+        "trap 'echo caught $?' USR1; trap 'echo bye' EXIT\n\
+         trap -p USR1; (trap -p; echo sub)\n\
+         sleep 10 & kill -USR1 $$; wait %1; echo \"wait $?\"; kill %1\n\
+         trap '' USR2; sh -c 'kill -USR2 $$; echo ignored'\n\
+         trap - USR2; sh -c 'kill -USR2 $$; echo unreachable'; echo \"reset $?\"\n\
+         trap 'echo no' NOSUCHSIG; echo \"bad $?\"",
+    );
+    // Leave out the `[1] pid` line for the background job
+    let lines: Vec<&str> = run.stdout.lines().filter(|line| !line.starts_with('[')).collect();
+    assert_eq!(lines, vec![
+        "trap -- 'echo caught $?' SIGUSR1",
+        "sub",
+        // `wait` is cut short by the signal, and the trap sees the status it left
+        "caught 138",
+        "wait 138",
+        // Ignored signals stay ignored in the programs the shell runs
+        "ignored",
+        "reset 140",
+        "bad 1",
+        "bye",
+    ]);
+}
+
+#[test]
+fn test_loop_in_pipeline() {
+    // A loop in the middle of a pipeline runs as its own process, so `x` isn't set afterwards
+    let run = chev("for x in b c a; do echo $x; done | sort; echo \"[$x]\"");
+    assert_eq!(run.stdout, "a\nb\nc\n[]\n");
+}
+
+#[test]
+fn test_function_in_pipeline() {
+    let run = chev("fn each { for a in \"$@\"; do echo \"[$a]\"; done; }; each \"a b\" \"\" | tr a-z A-Z");
+    assert_eq!(run.stdout, "[A B]\n[]\n");
+}
+
+#[test]
+fn test_script_arguments_named_like_subcommands() {
+    let dir = tempdir().unwrap();
+    let script = dir.path().join("parse");
+    std::fs::write(&script, "echo \"$# $@\"\n").unwrap();
+
+    // Past the script, `ai` and `parse` are its arguments rather than subcommands
+    let run = chev(&format!("{} --norc {} ai parse", env!("CARGO_BIN_EXE_chev"), script.display()));
+    assert_eq!(run.stdout, "2 ai parse\n");
+    assert_eq!(run.status, 0);
+}
+
+#[test]
+fn test_subshell_state() {
+    // A subshell is a new chev process, handed the shell's functions, parameters, traps and jobs
+    let run = chev(
+        "set -- one two; f() { echo \"f $1\"; }; trap '' USR2\n\
+         { f \"$2\"; echo \"$# $1\"; trap -p USR2; } | tr a-z A-Z\n\
+         sleep 5 &\n\
+         jobs | awk '{ print $2 }'\n\
+         (wait %1); echo \"wait $?\"; kill %1",
+    );
+    let lines: Vec<&str> = run.stdout.lines().filter(|line| !line.starts_with('[')).collect();
+    // The parent's job shows up, but only the parent can wait for it
+    assert_eq!(lines, vec!["F TWO", "2 ONE", "TRAP -- '' SIGUSR2", "Running", "wait 1"]);
+}